@@ -21,6 +21,7 @@ impl From<ExecutionError> for Diagnostic {
         Diagnostic {
             error_type: error_type.into(),
             error_message,
+            ..Default::default()
         }
     }
 }
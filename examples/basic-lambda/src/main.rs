@@ -1,7 +1,7 @@
 // This example requires the following input to succeed:
 // { "command": "do something" }
 
-use lambda_runtime::{service_fn, tracing, Error, LambdaEvent};
+use lambda_runtime::{Error, LambdaEvent};
 use serde::{Deserialize, Serialize};
 
 /// This is also a made-up example. Requests come into the runtime as unicode
@@ -22,16 +22,10 @@ struct Response {
     msg: String,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Error> {
-    // required to enable CloudWatch error logging by the runtime
-    tracing::init_default_subscriber();
-
-    let func = service_fn(my_handler);
-    lambda_runtime::run(func).await?;
-    Ok(())
-}
-
+// The attribute generates the `main` function, which initializes the default
+// tracing subscriber, required to enable CloudWatch error logging by the runtime,
+// and starts the runtime with this handler.
+#[lambda_runtime::main]
 pub(crate) async fn my_handler(event: LambdaEvent<Request>) -> Result<Response, Error> {
     // extract some useful info from the request
     let command = event.payload.command;
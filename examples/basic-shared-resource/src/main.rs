@@ -1,11 +1,16 @@
 // This example demonstrates use of shared resources such as DB connections
 // or local caches that can be initialized at the start of the runtime and
-// reused by subsequent lambda handler calls.
+// reused by subsequent lambda handler calls. The handler takes the shared
+// client with the `State` extractor, instead of capturing it in a closure.
 // Run it with the following input:
 // { "command": "do something" }
 
-use lambda_runtime::{service_fn, tracing, Error, LambdaEvent};
+use lambda_runtime::{
+    extract::{handler, Ctx, Payload, State},
+    tracing, Context, Error,
+};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 /// This is also a made-up example. Requests come into the runtime as unicode
 /// strings in json format, which can map to any structure that implements `serde::Deserialize`
@@ -47,12 +52,14 @@ async fn main() -> Result<(), Error> {
     // required to enable CloudWatch error logging by the runtime
     tracing::init_default_subscriber();
 
-    let client = SharedClient::new("Shared Client 1 (perhaps a database)");
-    let client_ref = &client;
-    lambda_runtime::run(service_fn(move |event: LambdaEvent<Request>| async move {
-        let command = event.payload.command;
-        Ok::<Response, Error>(client_ref.response(event.context.request_id, command))
-    }))
-    .await?;
-    Ok(())
+    let client = Arc::new(SharedClient::new("Shared Client 1 (perhaps a database)"));
+    lambda_runtime::run(handler(func).with_state(client)).await
+}
+
+async fn func(
+    Payload(request): Payload<Request>,
+    Ctx(context): Ctx<Context>,
+    State(client): State<Arc<SharedClient>>,
+) -> Result<Response, Error> {
+    Ok(client.response(context.request_id, request.command))
 }
@@ -1,9 +1,13 @@
-use lambda_http::{run, service_fn, tracing, Body, Error, Request, Response};
+use lambda_http::{Body, Error, Request, Response};
 
 /// This is the main body for the function.
 /// Write your code inside it.
 /// There are some code examples in the Runtime repository:
 /// - <https://github.com/awslabs/aws-lambda-rust-runtime/tree/main/examples>
+///
+/// The attribute generates the `main` function, which initializes the default
+/// tracing subscriber and starts the runtime with this handler.
+#[lambda_http::main]
 async fn function_handler(_event: Request) -> Result<Response<Body>, Error> {
     // Extract some useful information from the request
 
@@ -16,11 +20,3 @@ async fn function_handler(_event: Request) -> Result<Response<Body>, Error> {
         .map_err(Box::new)?;
     Ok(resp)
 }
-
-#[tokio::main]
-async fn main() -> Result<(), Error> {
-    // required to enable CloudWatch error logging by the runtime
-    tracing::init_default_subscriber();
-
-    run(service_fn(function_handler)).await
-}
@@ -3,7 +3,7 @@ use http_body_util::BodyExt;
 use hyper::{body::Incoming, server::conn::http1, service::service_fn};
 
 use hyper_util::rt::tokio::TokioIo;
-use lambda_runtime_api_client::Client;
+use lambda_runtime_api_client::{Client, RetryPolicy};
 use serde::Deserialize;
 use std::{
     convert::Infallible,
@@ -41,6 +41,7 @@ pub struct Extension<'a, E, L, T> {
     telemetry_processor: Option<T>,
     telemetry_buffering: Option<LogBuffering>,
     telemetry_port_number: u16,
+    retry_policy: RetryPolicy,
 }
 
 impl Extension<'_, Identity<LambdaEvent>, MakeIdentity<Vec<LambdaLog>>, MakeIdentity<Vec<LambdaTelemetry>>> {
@@ -58,6 +59,7 @@ impl Extension<'_, Identity<LambdaEvent>, MakeIdentity<Vec<LambdaLog>>, MakeIden
             telemetry_buffering: None,
             telemetry_processor: None,
             telemetry_port_number: DEFAULT_TELEMETRY_PORT_NUMBER,
+            retry_policy: RetryPolicy::none(),
         }
     }
 }
@@ -128,6 +130,7 @@ where
             telemetry_buffering: self.telemetry_buffering,
             telemetry_processor: self.telemetry_processor,
             telemetry_port_number: self.telemetry_port_number,
+            retry_policy: self.retry_policy,
         }
     }
 
@@ -150,6 +153,7 @@ where
             telemetry_buffering: self.telemetry_buffering,
             telemetry_processor: self.telemetry_processor,
             telemetry_port_number: self.telemetry_port_number,
+            retry_policy: self.retry_policy,
         }
     }
 
@@ -197,6 +201,7 @@ where
             telemetry_types: self.telemetry_types,
             telemetry_buffering: self.telemetry_buffering,
             telemetry_port_number: self.telemetry_port_number,
+            retry_policy: self.retry_policy,
         }
     }
 
@@ -225,6 +230,13 @@ where
         }
     }
 
+    /// Create a new [`Extension`] that retries the requests to the Extensions API which fail
+    /// because of transient errors, like connections refused by a restarting local emulator.
+    /// Requests are not retried by default.
+    pub fn with_retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Extension { retry_policy, ..self }
+    }
+
     /// Register the extension.
     ///
     /// Performs the
@@ -233,7 +245,7 @@ where
     /// extension, it is safe to call `lambda_runtime::run` once the future returned by this
     /// function resolves.
     pub async fn register(self) -> Result<RegisteredExtension<E>, Error> {
-        let client = &Client::builder().build()?;
+        let client = &Client::builder().with_retry_policy(self.retry_policy.clone()).build()?;
 
         let register_res = register(client, self.extension_name, self.events).await?;
 
@@ -336,6 +348,7 @@ where
             handler: register_res.handler,
             account_id: register_res.account_id,
             events_processor: self.events_processor,
+            retry_policy: self.retry_policy,
         })
     }
 
@@ -359,6 +372,7 @@ pub struct RegisteredExtension<E> {
     /// The Lambda function handler that AWS Lambda invokes
     pub handler: String,
     events_processor: E,
+    retry_policy: RetryPolicy,
 }
 
 impl<E> RegisteredExtension<E>
@@ -375,7 +389,7 @@ where
     /// [shutdown](https://docs.aws.amazon.com/lambda/latest/dg/lambda-runtime-environment.html#runtimes-lifecycle-shutdown)
     /// Lambda lifecycle phases.
     pub async fn run(self) -> Result<(), Error> {
-        let client = &Client::builder().build()?;
+        let client = &Client::builder().with_retry_policy(self.retry_policy).build()?;
         let mut ep = self.events_processor;
         let extension_id = &self.extension_id;
 
@@ -28,6 +28,7 @@ pub mod requests;
 #[cfg(feature = "tracing")]
 #[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
 pub use lambda_runtime_api_client::tracing;
+pub use lambda_runtime_api_client::RetryPolicy;
 
 /// Execute the given events processor
 pub async fn run<E>(events_processor: E) -> Result<(), Error>
@@ -72,6 +72,56 @@ pub use http::{self, Response};
 pub use lambda_runtime::tracing;
 use lambda_runtime::Diagnostic;
 pub use lambda_runtime::{self, service_fn, tower, Context, Error, LambdaEvent, Service};
+/// Declares the entry point of a Lambda function that handles HTTP requests.
+///
+/// The attribute goes on the handler function, and generates a `main` function that
+/// builds a Tokio runtime, initializes the default tracing subscriber, and starts the runtime
+/// with the handler, like [run]. It accepts the same arguments as [lambda_runtime::main].
+/// The handler takes the [Request], and when an `init` function is given, it receives its state
+/// with the [State](lambda_runtime::extract::State) extractor, like `lambda_runtime` handlers.
+///
+/// # Example
+/// ```no_run
+/// use lambda_http::{Body, Error, Request, Response};
+///
+/// #[lambda_http::main]
+/// async fn handler(_request: Request) -> Result<Response<Body>, Error> {
+///     Ok(Response::new("Hello AWS Lambda HTTP request".into()))
+/// }
+/// ```
+///
+/// With a state shared by all the requests:
+/// ```no_run
+/// use lambda_http::{lambda_runtime::extract::State, Body, Error, Request, Response};
+/// use std::sync::Arc;
+///
+/// struct Config {
+///     greeting: String,
+/// }
+///
+/// async fn load_config() -> Result<Arc<Config>, Error> {
+///     let greeting = std::env::var("GREETING")?;
+///     Ok(Arc::new(Config { greeting }))
+/// }
+///
+/// #[lambda_http::main(init = load_config)]
+/// async fn handler(State(config): State<Arc<Config>>, _request: Request) -> Result<Response<Body>, Error> {
+///     Ok(Response::new(config.greeting.clone().into()))
+/// }
+/// ```
+///
+/// The `State` extractor needs an `init` function to get the state from:
+/// ```compile_fail
+/// use lambda_http::{lambda_runtime::extract::State, Body, Error, Request, Response};
+///
+/// #[lambda_http::main]
+/// async fn handler(State(greeting): State<String>, _request: Request) -> Result<Response<Body>, Error> {
+///     Ok(Response::new(greeting.into()))
+/// }
+/// ```
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use lambda_runtime_macros::http_main as main;
 use request::RequestFuture;
 use response::ResponseFuture;
 
@@ -179,13 +229,25 @@ where
 
     fn call(&mut self, req: LambdaEvent<LambdaRequest>) -> Self::Future {
         let request_origin = req.payload.request_origin();
-        let event: Request = req.payload.into();
-        let fut = Box::pin(self.service.call(event.with_lambda_context(req.context)));
+        let event = request::from_lambda_event(req);
+        let fut = Box::pin(self.service.call(event));
 
         TransformResponse::Request(request_origin, fut)
     }
 }
 
+impl<R, S> Clone for Adapter<'_, R, S>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Adapter {
+            service: self.service.clone(),
+            _phantom_data: PhantomData,
+        }
+    }
+}
+
 /// Starts the Lambda Rust runtime and begins polling for events on the [Lambda
 /// Runtime APIs](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html).
 ///
@@ -201,6 +263,20 @@ where
     lambda_runtime::run(Adapter::from(handler)).await
 }
 
+/// Starts the Lambda Rust runtime and processes several HTTP requests at the same time
+/// on execution environments that support multi-concurrency.
+///
+/// See [`lambda_runtime::run_concurrent`] for more details.
+pub async fn run_concurrent<'a, R, S, E>(handler: S) -> Result<(), Error>
+where
+    S: Service<Request, Response = R, Error = E> + Clone,
+    S::Future: Send + 'a,
+    R: IntoResponse,
+    E: std::fmt::Debug + Into<Diagnostic>,
+{
+    lambda_runtime::run_concurrent(Adapter::from(handler)).await
+}
+
 #[cfg(test)]
 mod test_adapter {
     use std::task::{Context, Poll};
@@ -15,6 +15,7 @@ use crate::ext::extensions::{PathParameters, StageVariables};
     feature = "apigw_websockets"
 ))]
 use crate::ext::extensions::{QueryStringParameters, RawHttpPath};
+use crate::ext::RequestExt;
 #[cfg(feature = "alb")]
 use aws_lambda_events::alb::{AlbTargetGroupRequest, AlbTargetGroupRequestContext};
 #[cfg(any(feature = "apigw_rest", feature = "apigw_http", feature = "apigw_websockets"))]
@@ -27,6 +28,7 @@ use aws_lambda_events::apigw::{ApiGatewayV2httpRequest, ApiGatewayV2httpRequestC
 use aws_lambda_events::apigw::{ApiGatewayWebsocketProxyRequest, ApiGatewayWebsocketProxyRequestContext};
 use aws_lambda_events::{encodings::Body, query_map::QueryMap};
 use http::{header::HeaderName, HeaderMap, HeaderValue};
+use lambda_runtime::LambdaEvent;
 
 use serde::{Deserialize, Serialize};
 use serde_json::error::Error as JsonError;
@@ -426,6 +428,25 @@ impl From<LambdaRequest> for http::Request<Body> {
     }
 }
 
+/// Converts a Lambda event into a `http::Request<Body>` that carries the invocation context.
+///
+/// The `x-amzn-trace-id` header is taken from the invocation context when it's present,
+/// so the request doesn't depend on the process wide `_X_AMZN_TRACE_ID` environment variable
+/// that is not updated when the runtime processes invocations concurrently.
+pub(crate) fn from_lambda_event(event: LambdaEvent<LambdaRequest>) -> http::Request<Body> {
+    let (payload, context) = event.into_parts();
+    let mut req: http::Request<Body> = payload.into();
+    if let Some(header_value) = context
+        .xray_trace_id
+        .as_deref()
+        .and_then(|trace_id| HeaderValue::from_str(trace_id).ok())
+    {
+        req.headers_mut()
+            .insert(HeaderName::from_static("x-amzn-trace-id"), header_value);
+    }
+    req.with_lambda_context(context)
+}
+
 impl RequestContext {
     /// Returns the Api Gateway Authorizer information for a request.
     #[cfg(any(feature = "apigw_rest", feature = "apigw_http", feature = "apigw_websockets"))]
@@ -539,6 +560,21 @@ mod tests {
         assert!(result.is_ok(), "event was not parsed as expected {result:?}");
     }
 
+    #[test]
+    fn from_lambda_event_uses_context_trace_id() {
+        let input = include_str!("../tests/data/apigw_v2_proxy_request_minimal.json");
+        let payload: LambdaRequest = serde_json::from_str(input).expect("failed to parse request");
+        let mut context = lambda_runtime::Context::default();
+        context.xray_trace_id = Some("Root=1-5759e988-bd862e3fe1be46a994272793".to_string());
+
+        let req = from_lambda_event(LambdaEvent::new(payload, context));
+        assert_eq!(
+            req.headers()["x-amzn-trace-id"],
+            "Root=1-5759e988-bd862e3fe1be46a994272793"
+        );
+        assert!(req.lambda_context_ref().is_some());
+    }
+
     #[test]
     fn deserializes_minimal_apigw_http_request_events() {
         // from the docs
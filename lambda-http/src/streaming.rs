@@ -1,4 +1,8 @@
-use crate::{http::header::SET_COOKIE, request::LambdaRequest, Request, RequestExt};
+use crate::{
+    http::header::SET_COOKIE,
+    request::{self, LambdaRequest},
+    Request,
+};
 use bytes::Bytes;
 use core::{
     fmt::Debug,
@@ -59,12 +63,8 @@ where
     }
 
     fn call(&mut self, req: LambdaEvent<LambdaRequest>) -> Self::Future {
-        let event: Request = req.payload.into();
-        Box::pin(
-            self.service
-                .call(event.with_lambda_context(req.context))
-                .map_ok(into_stream_response),
-        )
+        let event = request::from_lambda_event(req);
+        Box::pin(self.service.call(event).map_ok(into_stream_response))
     }
 }
 
@@ -92,10 +92,7 @@ where
     B::Error: Into<Error> + Send + Debug,
 {
     ServiceBuilder::new()
-        .map_request(|req: LambdaEvent<LambdaRequest>| {
-            let event: Request = req.payload.into();
-            event.with_lambda_context(req.context)
-        })
+        .map_request(request::from_lambda_event)
         .service(handler)
         .map_response(into_stream_response)
 }
@@ -0,0 +1,222 @@
+//! Connectors to reach the Runtime API over transports other than TCP.
+//!
+//! The [Client](crate::Client) opens its connections with a [Connector], which wraps any
+//! [tower::Service] that takes the [Uri] of a request and returns a transport that implements
+//! [hyper::rt::Read] and [hyper::rt::Write]. Besides the TCP connector of `hyper_util`, this
+//! module includes a connector for Unix domain sockets, and an in-memory transport to test
+//! functions without binding any port.
+use crate::BoxError;
+use futures_channel::mpsc;
+use futures_util::{future, future::BoxFuture, FutureExt, StreamExt};
+use http::Uri;
+use hyper::rt::{Read, ReadBufCursor, Write};
+use hyper_util::{
+    client::legacy::connect::{Connected, Connection},
+    rt::TokioIo,
+};
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::DuplexStream;
+use tower::{util::BoxCloneSyncService, Service, ServiceExt};
+
+#[cfg(unix)]
+use std::{path::Path, sync::Arc};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+/// Size of the buffers of the in-memory connections.
+const DUPLEX_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Type-erased connector used by the [Client](crate::Client) to open connections.
+#[derive(Clone)]
+pub struct Connector(BoxCloneSyncService<Uri, Io, BoxError>);
+
+impl Connector {
+    /// Wrap a connector, like `hyper_util`'s `HttpConnector`, a [UnixConnector], or a [DuplexConnector].
+    pub fn new<C>(connector: C) -> Self
+    where
+        C: Service<Uri> + Clone + Send + Sync + 'static,
+        C::Response: Read + Write + Send + 'static,
+        C::Error: Into<BoxError>,
+        C::Future: Send + 'static,
+    {
+        Self(BoxCloneSyncService::new(
+            connector.map_response(Io::new).map_err(Into::into),
+        ))
+    }
+}
+
+impl fmt::Debug for Connector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connector").finish_non_exhaustive()
+    }
+}
+
+impl Service<Uri> for Connector {
+    type Response = Io;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Io, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        self.0.call(uri)
+    }
+}
+
+trait Transport: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Transport for T {}
+
+/// Connection opened by a [Connector].
+pub struct Io(Pin<Box<dyn Transport>>);
+
+impl Io {
+    fn new<T: Read + Write + Send + 'static>(transport: T) -> Self {
+        Self(Box::pin(transport))
+    }
+}
+
+impl fmt::Debug for Io {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Io").finish_non_exhaustive()
+    }
+}
+
+impl Connection for Io {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl Read for Io {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: ReadBufCursor<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().0.as_mut().poll_read(cx, buf)
+    }
+}
+
+impl Write for Io {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.get_mut().0.as_mut().poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().0.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().0.as_mut().poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().0.as_mut().poll_write_vectored(cx, bufs)
+    }
+}
+
+/// Connector that opens the connections on a Unix domain socket, whatever the URI of the request.
+#[cfg(unix)]
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+#[derive(Clone, Debug)]
+pub struct UnixConnector {
+    path: Arc<Path>,
+}
+
+#[cfg(unix)]
+impl UnixConnector {
+    /// Create a connector to the socket at `path`.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().into(),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Service<Uri> for UnixConnector {
+    type Response = TokioIo<UnixStream>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Self::Response>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.path.clone();
+        async move { UnixStream::connect(path).await.map(TokioIo::new) }.boxed()
+    }
+}
+
+/// Create an in-memory transport.
+///
+/// Every connection opened by the [DuplexConnector] is accepted by the [DuplexListener],
+/// which can serve it like a TCP stream, for example with `hyper`'s server.
+///
+/// # Example
+/// ```
+/// use lambda_runtime_api_client::{connect, Client};
+///
+/// let (connector, listener) = connect::duplex();
+/// let client = Client::builder()
+///     .with_endpoint("http://localhost".parse().unwrap())
+///     .with_connector(connector)
+///     .build();
+/// ```
+pub fn duplex() -> (DuplexConnector, DuplexListener) {
+    let (sender, receiver) = mpsc::unbounded();
+    (DuplexConnector { sender }, DuplexListener { receiver })
+}
+
+/// Connector that opens in-memory connections to a [DuplexListener]. See [duplex].
+#[derive(Clone, Debug)]
+pub struct DuplexConnector {
+    sender: mpsc::UnboundedSender<DuplexStream>,
+}
+
+impl Service<Uri> for DuplexConnector {
+    type Response = TokioIo<DuplexStream>;
+    type Error = io::Error;
+    type Future = future::Ready<io::Result<Self::Response>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let (client, server) = tokio::io::duplex(DUPLEX_BUFFER_SIZE);
+        let result = match self.sender.unbounded_send(server) {
+            Ok(()) => Ok(TokioIo::new(client)),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "the in-memory listener is closed",
+            )),
+        };
+        future::ready(result)
+    }
+}
+
+/// Server side of the in-memory connections opened by a [DuplexConnector]. See [duplex].
+#[derive(Debug)]
+pub struct DuplexListener {
+    receiver: mpsc::UnboundedReceiver<DuplexStream>,
+}
+
+impl DuplexListener {
+    /// Wait for the next connection. It returns `None` when all the connectors have been dropped.
+    pub async fn accept(&mut self) -> Option<DuplexStream> {
+        self.receiver.next().await
+    }
+}
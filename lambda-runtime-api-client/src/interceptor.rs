@@ -0,0 +1,219 @@
+//! Hooks to observe the requests that the [Client](crate::Client) sends to the Runtime API.
+//!
+//! Every request goes through the [Interceptor]s of the client, which receive the request before
+//! it's sent, and an [Exchange] with its latency and the number of bytes sent once the response
+//! arrives. With the `tracing` feature, every request is also wrapped in a `DEBUG` span named
+//! `Runtime API request`, with the same information.
+//!
+//! # Example
+//! ```
+//! use hyper::body::Incoming;
+//! use lambda_runtime_api_client::{
+//!     interceptor::{Exchange, Interceptor},
+//!     Client,
+//! };
+//!
+//! struct LogLatency;
+//!
+//! impl Interceptor for LogLatency {
+//!     fn on_response(&self, exchange: &Exchange, response: &http::Response<Incoming>) {
+//!         println!(
+//!             "{} {} took {:?}, {} bytes sent",
+//!             exchange.uri.path(),
+//!             response.status(),
+//!             exchange.latency,
+//!             exchange.bytes_sent
+//!         );
+//!     }
+//! }
+//!
+//! let client = Client::builder()
+//!     .with_endpoint("http://localhost:9001".parse().unwrap())
+//!     .with_interceptor(LogLatency)
+//!     .build();
+//! ```
+use crate::{body::Body, BoxError, Connector};
+use bytes::Bytes;
+use futures_util::{future::BoxFuture, FutureExt};
+use http::{Method, Request, Response, Uri};
+use http_body::{Frame, SizeHint};
+use hyper::body::Incoming;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// Summary of a request sent to the Runtime API.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Exchange {
+    /// Method of the request.
+    pub method: Method,
+    /// URI of the request.
+    pub uri: Uri,
+    /// Time between sending the request and receiving the response headers, or the error.
+    ///
+    /// For `/runtime/invocation/next`, it's how long the runtime waited for an invocation.
+    /// For streaming responses, it includes the time spent streaming the body.
+    pub latency: Duration,
+    /// Number of bytes of the request body sent.
+    pub bytes_sent: u64,
+}
+
+/// Callbacks called for every request sent by a [Client](crate::Client).
+///
+/// When a request is retried, the callbacks are called for every attempt.
+pub trait Interceptor: Send + Sync {
+    /// Called before a request is sent. The request can be modified, for example to add headers.
+    fn on_request(&self, request: &mut Request<Body>) {
+        let _ = request;
+    }
+
+    /// Called when the headers of the response are received.
+    fn on_response(&self, exchange: &Exchange, response: &Response<Incoming>) {
+        let _ = (exchange, response);
+    }
+
+    /// Called when the request fails.
+    fn on_error(&self, exchange: &Exchange, error: &BoxError) {
+        let _ = (exchange, error);
+    }
+}
+
+impl<T: Interceptor + ?Sized> Interceptor for Box<T> {
+    fn on_request(&self, request: &mut Request<Body>) {
+        (**self).on_request(request)
+    }
+
+    fn on_response(&self, exchange: &Exchange, response: &Response<Incoming>) {
+        (**self).on_response(exchange, response)
+    }
+
+    fn on_error(&self, exchange: &Exchange, error: &BoxError) {
+        (**self).on_error(exchange, error)
+    }
+}
+
+/// Interceptors of a client, called in the order they were added.
+#[derive(Clone, Default)]
+pub(crate) struct Interceptors(Vec<Arc<dyn Interceptor>>);
+
+impl fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interceptors").field("len", &self.0.len()).finish()
+    }
+}
+
+impl Interceptors {
+    pub(crate) fn push(&mut self, interceptor: Arc<dyn Interceptor>) {
+        self.0.push(interceptor);
+    }
+
+    /// Send a request with `client`, calling the interceptors around it.
+    pub(crate) fn send(
+        &self,
+        client: &hyper_util::client::legacy::Client<Connector, Body>,
+        mut req: Request<Body>,
+    ) -> BoxFuture<'static, Result<Response<Incoming>, BoxError>> {
+        for interceptor in &self.0 {
+            interceptor.on_request(&mut req);
+        }
+        let method = req.method().clone();
+        let uri = req.uri().clone();
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        let req = req.map(|body| {
+            Body::new(CountingBody {
+                inner: body,
+                bytes_sent: bytes_sent.clone(),
+            })
+        });
+
+        #[cfg(feature = "tracing")]
+        let span = ::tracing::debug_span!(
+            "Runtime API request",
+            method = %method,
+            path = uri.path(),
+            status = ::tracing::field::Empty,
+            latency_ms = ::tracing::field::Empty,
+            bytes_sent = ::tracing::field::Empty,
+        );
+        #[cfg(feature = "tracing")]
+        let record_span = span.clone();
+
+        let interceptors = self.clone();
+        let start = Instant::now();
+        let response = client.request(req);
+        let future = async move {
+            let result = response.await.map_err(BoxError::from);
+            let exchange = Exchange {
+                method,
+                uri,
+                latency: start.elapsed(),
+                bytes_sent: bytes_sent.load(Ordering::Relaxed),
+            };
+
+            #[cfg(feature = "tracing")]
+            {
+                record_span.record("latency_ms", exchange.latency.as_millis() as u64);
+                record_span.record("bytes_sent", exchange.bytes_sent);
+                if let Ok(response) = &result {
+                    record_span.record("status", response.status().as_u16());
+                }
+            }
+
+            match &result {
+                Ok(response) => interceptors
+                    .0
+                    .iter()
+                    .for_each(|interceptor| interceptor.on_response(&exchange, response)),
+                Err(err) => interceptors
+                    .0
+                    .iter()
+                    .for_each(|interceptor| interceptor.on_error(&exchange, err)),
+            }
+            result
+        };
+
+        #[cfg(feature = "tracing")]
+        let future = ::tracing::Instrument::instrument(future, span);
+        future.boxed()
+    }
+}
+
+/// Body that counts the bytes of data that go through it.
+struct CountingBody {
+    inner: Body,
+    bytes_sent: Arc<AtomicU64>,
+}
+
+impl http_body::Body for CountingBody {
+    type Data = Bytes;
+    type Error = crate::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &frame {
+            if let Some(data) = frame.data_ref() {
+                self.bytes_sent.fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+        }
+        frame
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
@@ -5,22 +5,35 @@
 
 //! This crate includes a base HTTP client to interact with
 //! the AWS Lambda Runtime API.
-use futures_util::{future::BoxFuture, FutureExt, TryFutureExt};
+use futures_util::{future::BoxFuture, FutureExt};
 use http::{
     uri::{PathAndQuery, Scheme},
     Request, Response, Uri,
 };
+use http_body::Body as _;
 use hyper::body::Incoming;
 use hyper_util::client::legacy::connect::HttpConnector;
-use std::{convert::TryInto, fmt::Debug, future};
+use std::{convert::TryInto, fmt::Debug, future, sync::Arc};
+use tower::Service;
+
+pub use connect::Connector;
+use interceptor::{Interceptor, Interceptors};
 
 const USER_AGENT_HEADER: &str = "User-Agent";
 const DEFAULT_USER_AGENT: &str = concat!("aws-lambda-rust/", env!("CARGO_PKG_VERSION"));
 const CUSTOM_USER_AGENT: Option<&str> = option_env!("LAMBDA_RUNTIME_USER_AGENT");
+/// Prefix of the `AWS_LAMBDA_RUNTIME_API` values that point to a Unix domain socket.
+const UNIX_ENDPOINT_PREFIX: &str = "unix://";
+/// Base URI of the requests sent over a Unix domain socket.
+const UNIX_ENDPOINT_BASE: &str = "http://localhost";
 
 mod error;
 pub use error::*;
+mod retry;
+pub use retry::RetryPolicy;
 pub mod body;
+pub mod connect;
+pub mod interceptor;
 
 #[cfg(feature = "tracing")]
 #[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
@@ -32,15 +45,22 @@ pub struct Client {
     /// The runtime API URI
     pub base: Uri,
     /// The client that manages the API connections
-    pub client: hyper_util::client::legacy::Client<HttpConnector, body::Body>,
+    ///
+    /// Requests sent directly with it skip the [RetryPolicy] and the
+    /// [interceptors](ClientBuilder::with_interceptor) of the client, use [Client::call] instead.
+    pub client: hyper_util::client::legacy::Client<Connector, body::Body>,
+    retry_policy: RetryPolicy,
+    interceptors: Interceptors,
 }
 
 impl Client {
     /// Create a builder struct to configure the client.
     pub fn builder() -> ClientBuilder {
         ClientBuilder {
-            connector: HttpConnector::new(),
+            connector: None,
             uri: None,
+            retry_policy: RetryPolicy::none(),
+            interceptors: Interceptors::default(),
         }
     }
 }
@@ -48,6 +68,10 @@ impl Client {
 impl Client {
     /// Send a given request to the Runtime API.
     /// Use the client's base URI to ensure the API endpoint is correct.
+    ///
+    /// The request is retried according to the [RetryPolicy] of the client
+    /// when its body has a known size. Every attempt goes through the
+    /// [interceptors](ClientBuilder::with_interceptor) of the client.
     pub fn call(&self, req: Request<body::Body>) -> BoxFuture<'static, Result<Response<Incoming>, BoxError>> {
         // NOTE: This method returns a boxed future such that the future has a static lifetime.
         //       Due to limitations around the Rust async implementation as of Mar 2024, this is
@@ -56,22 +80,66 @@ impl Client {
             Ok(req) => req,
             Err(err) => return future::ready(Err(err)).boxed(),
         };
-        self.client.request(req).map_err(Into::into).boxed()
+        // Streaming bodies can't be replayed, they're always sent once.
+        if self.retry_policy.max_attempts() == 1 || req.body().size_hint().exact().is_none() {
+            return self.interceptors.send(&self.client, req);
+        }
+
+        let client = self.client.clone();
+        let retry_policy = self.retry_policy.clone();
+        let interceptors = self.interceptors.clone();
+        async move {
+            let (parts, body) = req.into_parts();
+            let body = body.collect().await?.to_bytes();
+            let mut attempt = 1;
+            loop {
+                let mut req = Request::new(body::Body::from(body.clone()));
+                *req.method_mut() = parts.method.clone();
+                *req.uri_mut() = parts.uri.clone();
+                *req.version_mut() = parts.version;
+                *req.headers_mut() = parts.headers.clone();
+
+                let result = interceptors.send(&client, req).await;
+                match retry_policy.backoff(attempt, &parts.method, &result) {
+                    Some(backoff) => {
+                        #[cfg(feature = "tracing")]
+                        ::tracing::warn!(
+                            attempt,
+                            ?backoff,
+                            uri = %parts.uri,
+                            "request to the Runtime API failed, retrying"
+                        );
+                        tokio::time::sleep(backoff).await;
+                        attempt += 1;
+                    }
+                    None => return result,
+                }
+            }
+        }
+        .boxed()
     }
 
-    /// Create a new client with a given base URI and HTTP connector.
-    fn with(base: Uri, connector: HttpConnector) -> Self {
+    /// Create a new client with a given base URI, connector, retry policy, and interceptors.
+    fn with(base: Uri, connector: Connector, retry_policy: RetryPolicy, interceptors: Interceptors) -> Self {
         let client = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
             .http1_max_buf_size(1024 * 1024)
             .build(connector);
-        Self { base, client }
+        Self {
+            base,
+            client,
+            retry_policy,
+            interceptors,
+        }
     }
 
     fn set_origin<B>(&self, req: Request<B>) -> Result<Request<B>, BoxError> {
         let (mut parts, body) = req.into_parts();
         let (scheme, authority, base_path) = {
             let scheme = self.base.scheme().unwrap_or(&Scheme::HTTP);
-            let authority = self.base.authority().expect("Authority not found");
+            let authority = self
+                .base
+                .authority()
+                .ok_or_else(|| format!("Runtime API endpoint `{}` has no authority", self.base))?;
             let base_path = self.base.path().trim_end_matches('/');
             (scheme, authority, base_path)
         };
@@ -92,35 +160,99 @@ impl Client {
 
 /// Builder implementation to construct any Runtime API clients.
 pub struct ClientBuilder {
-    connector: HttpConnector,
+    connector: Option<Connector>,
     uri: Option<http::Uri>,
+    retry_policy: RetryPolicy,
+    interceptors: Interceptors,
 }
 
 impl ClientBuilder {
-    /// Create a new builder with a given HTTP connector.
-    pub fn with_connector(self, connector: HttpConnector) -> ClientBuilder {
+    /// Open the connections to the Runtime API with the given connector.
+    ///
+    /// The connector can be any [tower::Service] that takes the [Uri] of a request and
+    /// returns a transport, like `hyper_util`'s `HttpConnector`, which is used by default,
+    /// a [connect::UnixConnector], or a [connect::DuplexConnector].
+    pub fn with_connector<C>(self, connector: C) -> ClientBuilder
+    where
+        C: Service<Uri> + Clone + Send + Sync + 'static,
+        C::Response: hyper::rt::Read + hyper::rt::Write + Send + 'static,
+        C::Error: Into<BoxError>,
+        C::Future: Send + 'static,
+    {
         ClientBuilder {
-            connector,
-            uri: self.uri,
+            connector: Some(Connector::new(connector)),
+            ..self
         }
     }
 
+    /// Connect to a Runtime API that listens on the Unix domain socket at `path`.
+    #[cfg(unix)]
+    #[cfg_attr(docsrs, doc(cfg(unix)))]
+    pub fn with_unix_socket(self, path: impl AsRef<std::path::Path>) -> Self {
+        self.with_connector(connect::UnixConnector::new(path))
+            .with_endpoint(Uri::from_static(UNIX_ENDPOINT_BASE))
+    }
+
     /// Create a new builder with a given base URI.
     /// Inherits all other attributes from the existent builder.
     pub fn with_endpoint(self, uri: http::Uri) -> Self {
         Self { uri: Some(uri), ..self }
     }
 
+    /// Retry the requests that fail because of transient errors with the given policy.
+    /// Requests are not retried by default.
+    pub fn with_retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self { retry_policy, ..self }
+    }
+
+    /// Add an interceptor called for every request sent to the Runtime API.
+    /// Interceptors are called in the order they're added.
+    ///
+    /// See the [interceptor] module for more details.
+    pub fn with_interceptor(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
     /// Create the new client to interact with the Runtime API.
+    ///
+    /// When no endpoint has been configured with [`ClientBuilder::with_endpoint`], the endpoint is read
+    /// from the `AWS_LAMBDA_RUNTIME_API` environment variable. The variable can contain a
+    /// `unix://` URI, like `unix:///tmp/runtime.sock`, to connect to a Unix domain socket.
+    /// This method returns an error if the variable is missing or it doesn't contain a valid URI.
     pub fn build(self) -> Result<Client, Error> {
-        let uri = match self.uri {
-            Some(uri) => uri,
+        let builder = match self.uri {
+            Some(_) => self,
             None => {
-                let uri = std::env::var("AWS_LAMBDA_RUNTIME_API").expect("Missing AWS_LAMBDA_RUNTIME_API env var");
-                uri.try_into().expect("Unable to convert to URL")
+                let endpoint = std::env::var("AWS_LAMBDA_RUNTIME_API")
+                    .map_err(|_| Error::new("Missing AWS_LAMBDA_RUNTIME_API env var"))?;
+                self.with_env_endpoint(endpoint)?
             }
         };
-        Ok(Client::with(uri, self.connector))
+        let uri = builder.uri.expect("the endpoint is always set");
+        let connector = builder
+            .connector
+            .unwrap_or_else(|| Connector::new(HttpConnector::new()));
+        Ok(Client::with(uri, connector, builder.retry_policy, builder.interceptors))
+    }
+
+    fn with_env_endpoint(self, endpoint: String) -> Result<Self, Error> {
+        let Some(path) = endpoint.strip_prefix(UNIX_ENDPOINT_PREFIX) else {
+            let uri = endpoint
+                .try_into()
+                .map_err(|err| Error::new(format!("Unable to convert AWS_LAMBDA_RUNTIME_API to URL: {err}")))?;
+            return Ok(self.with_endpoint(uri));
+        };
+        // A custom connector is kept, it's in charge of reaching the socket.
+        if self.connector.is_some() {
+            return Ok(self.with_endpoint(Uri::from_static(UNIX_ENDPOINT_BASE)));
+        }
+        #[cfg(unix)]
+        return Ok(self.with_unix_socket(path));
+        #[cfg(not(unix))]
+        return Err(Error::new(format!(
+            "Unable to connect to {path}: Unix domain sockets are not supported on this platform"
+        )));
     }
 }
 
@@ -140,6 +272,7 @@ pub fn build_request() -> http::request::Builder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[test]
     fn test_set_origin() {
@@ -156,6 +289,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_build_without_endpoint() {
+        if std::env::var("AWS_LAMBDA_RUNTIME_API").is_err() {
+            let err = Client::builder().build().unwrap_err();
+            assert_eq!("Missing AWS_LAMBDA_RUNTIME_API env var", err.to_string());
+        }
+    }
+
     #[test]
     fn test_set_origin_with_base_path() {
         let base = "http://localhost:9001/foo";
@@ -182,4 +323,209 @@ mod tests {
             &req.uri().to_string()
         );
     }
+
+    #[tokio::test]
+    async fn test_retry_closed_connections() {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            // Close the first connection without responding, like a restarting emulator.
+            drop(listener.accept().await.unwrap());
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _request = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let retry_policy = RetryPolicy::new().with_backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let client = Client::builder()
+            .with_endpoint(base.parse().unwrap())
+            .with_retry_policy(retry_policy)
+            .build()
+            .unwrap();
+        let req = build_request()
+            .uri("/2018-06-01/runtime/invocation/next")
+            .body(body::Body::empty())
+            .unwrap();
+        let res = client.call(req).await.unwrap();
+        assert_eq!(res.status(), http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_after_sending_post() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+        use tokio::{io::AsyncReadExt, net::TcpListener};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let server_attempts = attempts.clone();
+        tokio::spawn(async move {
+            // Read every request, and close the connection without responding.
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _request = stream.read(&mut buf).await.unwrap();
+                server_attempts.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        let retry_policy = RetryPolicy::new().with_backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let client = Client::builder()
+            .with_endpoint(base.parse().unwrap())
+            .with_retry_policy(retry_policy)
+            .build()
+            .unwrap();
+        let req = build_request()
+            .method(http::Method::POST)
+            .uri("/2018-06-01/runtime/invocation/id/response")
+            .body(body::Body::from("{}"))
+            .unwrap();
+        assert!(client.call(req).await.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    async fn respond_ok<S>(mut stream: S)
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut buf = [0; 1024];
+        let _request = stream.read(&mut buf).await.unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .await
+            .unwrap();
+    }
+
+    fn next_request() -> Request<body::Body> {
+        build_request()
+            .uri("/2018-06-01/runtime/invocation/next")
+            .body(body::Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_duplex_connector() {
+        let (connector, mut listener) = connect::duplex();
+        let server = tokio::spawn(async move { respond_ok(listener.accept().await.unwrap()).await });
+
+        let client = Client::builder()
+            .with_endpoint("http://localhost".parse().unwrap())
+            .with_connector(connector.clone())
+            .build()
+            .unwrap();
+        let res = client.call(next_request()).await.unwrap();
+        assert_eq!(res.status(), http::StatusCode::OK);
+
+        // The listener is dropped once the server is done.
+        server.await.unwrap();
+        let client = Client::builder()
+            .with_endpoint("http://localhost".parse().unwrap())
+            .with_connector(connector)
+            .build()
+            .unwrap();
+        assert!(client.call(next_request()).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket() {
+        let path = std::env::temp_dir().join(format!("lambda-runtime-api-client-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move { respond_ok(listener.accept().await.unwrap().0).await });
+
+        let client = Client::builder()
+            .with_env_endpoint(format!("unix://{}", path.display()))
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(client.base, UNIX_ENDPOINT_BASE);
+        let res = client.call(next_request()).await.unwrap();
+        assert_eq!(res.status(), http::StatusCode::OK);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_set_origin_without_authority() {
+        let client = Client::builder()
+            .with_endpoint("/2018-06-01".parse().unwrap())
+            .build()
+            .unwrap();
+        let req = build_request().uri("/runtime/invocation/next").body(()).unwrap();
+        let err = client.set_origin(req).unwrap_err();
+        assert_eq!("Runtime API endpoint `/2018-06-01` has no authority", err.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_interceptors() {
+        use interceptor::Exchange;
+        use std::sync::Mutex;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<Exchange>>);
+
+        impl Interceptor for Arc<Recorder> {
+            fn on_request(&self, request: &mut Request<body::Body>) {
+                request
+                    .headers_mut()
+                    .insert("x-intercepted", http::HeaderValue::from_static("true"));
+            }
+
+            fn on_response(&self, exchange: &Exchange, _response: &Response<Incoming>) {
+                self.0.lock().unwrap().push(exchange.clone());
+            }
+        }
+
+        let (connector, mut listener) = connect::duplex();
+        let server = tokio::spawn(async move {
+            let mut stream = listener.accept().await.unwrap();
+            let mut buf = vec![0; 1024];
+            let len = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 202 Accepted\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(buf[..len].to_vec()).unwrap()
+        });
+
+        let recorder = Arc::new(Recorder::default());
+        let client = Client::builder()
+            .with_endpoint("http://localhost".parse().unwrap())
+            .with_connector(connector)
+            .with_interceptor(recorder.clone())
+            .build()
+            .unwrap();
+        let req = build_request()
+            .method("POST")
+            .uri("/2018-06-01/runtime/invocation/id/response")
+            .body(body::Body::from("hello"))
+            .unwrap();
+        let res = client.call(req).await.unwrap();
+        assert_eq!(res.status(), http::StatusCode::ACCEPTED);
+
+        let request = server.await.unwrap();
+        assert!(request.contains("x-intercepted: true"), "{request}");
+        assert!(request.ends_with("\r\n\r\nhello"), "{request}");
+
+        let exchanges = recorder.0.lock().unwrap();
+        assert_eq!(exchanges.len(), 1);
+        assert_eq!(exchanges[0].method, http::Method::POST);
+        assert_eq!(exchanges[0].uri.path(), "/2018-06-01/runtime/invocation/id/response");
+        assert_eq!(exchanges[0].bytes_sent, 5);
+    }
 }
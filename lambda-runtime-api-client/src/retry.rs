@@ -0,0 +1,227 @@
+use crate::BoxError;
+use http::{Method, StatusCode};
+use std::{error::Error as StdError, fmt, io, sync::Arc, time::Duration};
+
+type RetryableError = Arc<dyn Fn(&Method, &BoxError) -> bool + Send + Sync>;
+
+/// Policy to retry the requests to the Runtime API that fail because of transient errors,
+/// like connections refused by restarting local emulators.
+///
+/// Requests are attempted up to [max attempts](RetryPolicy::with_max_attempts) times, with an
+/// exponential backoff between attempts. By default, a request is retried when the connection
+/// can't be opened, and when the Runtime API responds with a `502`, `503`, or `504` status code.
+/// `GET` requests, like `/next`, are also retried when the connection is closed before the response
+/// is received. Other requests are not, since the API may have already processed them, and sending
+/// a `/response` or an extension registration twice fails. The last response or error is returned
+/// when all the attempts fail.
+///
+/// Only requests with a body of a known size are retried, streaming responses are sent once.
+///
+/// # Example
+/// ```
+/// use lambda_runtime_api_client::{Client, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new()
+///     .with_max_attempts(5)
+///     .with_backoff(Duration::from_millis(20), Duration::from_secs(1));
+/// let client = Client::builder()
+///     .with_endpoint("http://localhost:9001".parse().unwrap())
+///     .with_retry_policy(policy)
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retryable_error: RetryableError,
+    retryable_statuses: Vec<StatusCode>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("retryable_statuses", &self.retryable_statuses)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy that attempts requests up to three times, waiting 50ms before
+    /// the first retry, and retries on transient connection errors and `502`, `503`, and `504` responses.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            retryable_error: Arc::new(is_transient_error),
+            retryable_statuses: vec![
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+        }
+    }
+
+    /// Create a policy that never retries requests. This is what the client uses by default.
+    pub fn none() -> Self {
+        Self::new().with_max_attempts(1)
+    }
+
+    /// Set the maximum number of attempts of a request, including the first one.
+    /// A value of `0` is treated as `1`.
+    pub fn with_max_attempts(self, max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            ..self
+        }
+    }
+
+    /// Set the backoff before the first retry, and the maximum backoff. The backoff doubles
+    /// after every attempt.
+    pub fn with_backoff(self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            initial_backoff,
+            max_backoff,
+            ..self
+        }
+    }
+
+    /// Decide which errors are retried, from the method of the request and the error.
+    /// By default, only the errors that happen before the request could be processed are retried.
+    pub fn with_retryable_error<F>(self, retryable_error: F) -> Self
+    where
+        F: Fn(&Method, &BoxError) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            retryable_error: Arc::new(retryable_error),
+            ..self
+        }
+    }
+
+    /// Set the response status codes that are retried.
+    pub fn with_retryable_statuses(self, statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+        Self {
+            retryable_statuses: statuses.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// The maximum number of attempts of a request.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Backoff to wait before the next attempt, or `None` when the request must not be retried.
+    pub(crate) fn backoff<T>(
+        &self,
+        attempt: u32,
+        method: &Method,
+        result: &Result<http::Response<T>, BoxError>,
+    ) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let retryable = match result {
+            Ok(response) => self.retryable_statuses.contains(&response.status()),
+            Err(err) => (self.retryable_error)(method, err),
+        };
+        retryable.then(|| {
+            let backoff = self.initial_backoff.saturating_mul(2u32.saturating_pow(attempt - 1));
+            backoff.min(self.max_backoff)
+        })
+    }
+}
+
+/// Whether the connection couldn't be opened, so the request was never sent, or for `GET`
+/// requests, whether the connection was closed before the response was received.
+fn is_transient_error(method: &Method, err: &BoxError) -> bool {
+    let replayable = method == Method::GET;
+    let mut source: Option<&(dyn StdError + 'static)> = Some(err.as_ref());
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<hyper_util::client::legacy::Error>() {
+            if err.is_connect() {
+                return true;
+            }
+        }
+        if let Some(err) = err.downcast_ref::<hyper::Error>() {
+            if replayable && (err.is_incomplete_message() || err.is_closed() || err.is_canceled()) {
+                return true;
+            }
+        }
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            match err.kind() {
+                io::ErrorKind::ConnectionRefused => return true,
+                io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof
+                    if replayable =>
+                {
+                    return true
+                }
+                _ => {}
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: StatusCode) -> Result<http::Response<()>, BoxError> {
+        let mut response = http::Response::new(());
+        *response.status_mut() = status;
+        Ok(response)
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy::new()
+            .with_max_attempts(4)
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(30));
+        let unavailable = response(StatusCode::SERVICE_UNAVAILABLE);
+        let get = &Method::GET;
+        assert_eq!(policy.backoff(1, get, &unavailable), Some(Duration::from_millis(10)));
+        assert_eq!(policy.backoff(2, get, &unavailable), Some(Duration::from_millis(20)));
+        assert_eq!(policy.backoff(3, get, &unavailable), Some(Duration::from_millis(30)));
+        assert_eq!(policy.backoff(4, get, &unavailable), None);
+        assert_eq!(policy.backoff(1, get, &response(StatusCode::OK)), None);
+        assert_eq!(
+            policy.backoff(1, get, &response(StatusCode::INTERNAL_SERVER_ERROR)),
+            None
+        );
+        assert_eq!(RetryPolicy::none().backoff(1, get, &unavailable), None);
+    }
+
+    #[test]
+    fn retryable_errors() {
+        let policy = RetryPolicy::new();
+        let reset = || -> BoxError { io::Error::from(io::ErrorKind::ConnectionReset).into() };
+        let refused = || -> BoxError { io::Error::from(io::ErrorKind::ConnectionRefused).into() };
+        assert!(policy.backoff::<()>(1, &Method::GET, &Err(reset())).is_some());
+        assert!(policy.backoff::<()>(1, &Method::POST, &Err(reset())).is_none());
+        assert!(policy.backoff::<()>(1, &Method::POST, &Err(refused())).is_some());
+        assert!(policy
+            .backoff::<()>(1, &Method::GET, &Err("invalid request".into()))
+            .is_none());
+
+        let policy = policy.with_retryable_error(|_, err| err.to_string() == "invalid request");
+        assert!(policy
+            .backoff::<()>(1, &Method::POST, &Err("invalid request".into()))
+            .is_some());
+    }
+}
@@ -0,0 +1,345 @@
+use serde_json::{Map, Value};
+use std::fmt::{self, Write as _};
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::{
+        format::{JsonFields, Writer},
+        time::{FormatTime, SystemTime},
+        FmtContext, FormatEvent, FormattedFields,
+    },
+    registry::LookupSpan,
+};
+
+/// Value written instead of the value of the redacted fields.
+const REDACTED: &str = "[REDACTED]";
+
+/// Span fields that are written as top level keys of the log lines, with their key.
+const INVOCATION_FIELDS: [(&str, &str); 4] = [
+    ("requestId", "requestId"),
+    ("xrayTraceId", "xrayTraceId"),
+    ("functionArn", "functionArn"),
+    ("faas.coldstart", "coldStart"),
+];
+
+/// [FormatEvent] implementation that formats log lines as JSON objects with the keys expected by
+/// [Lambda's advanced logging controls](https://docs.aws.amazon.com/lambda/latest/dg/monitoring-cloudwatchlogs-advanced.html).
+///
+/// Every log line starts with `timestamp`, `level`, `requestId` and `message`, the same keys used by
+/// the managed runtimes, followed by `xrayTraceId`, `functionArn`, `coldStart`, the fields of the event,
+/// and the `spans` that contain the event. The invocation metadata comes from the fields of the span
+/// created by the runtime's `TracingLayer`, so it's only written for the events emitted inside that span.
+/// Events of spawned tasks are attributed to the invocation when the task is
+/// [instrumented](tracing::Instrument) with the current span.
+///
+/// The formatter must be used with the [JsonFields] field formatter.
+///
+/// # Example
+/// ```
+/// use lambda_runtime_api_client::tracing::{subscriber, LambdaJsonFormat};
+///
+/// subscriber::fmt()
+///     .fmt_fields(subscriber::fmt::format::JsonFields::new())
+///     .event_format(LambdaJsonFormat::new().with_redacted_fields(["password", "authorization"]))
+///     .init();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LambdaJsonFormat {
+    redacted_fields: Vec<String>,
+}
+
+impl LambdaJsonFormat {
+    /// Create a new formatter without redacted fields.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the value of the event and span fields with these names by `[REDACTED]`.
+    /// Field names are compared case-insensitively.
+    pub fn with_redacted_fields<I, F>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = F>,
+        F: Into<String>,
+    {
+        self.redacted_fields.extend(fields.into_iter().map(Into::into));
+        self
+    }
+
+    fn is_redacted(&self, field: &str) -> bool {
+        self.redacted_fields.iter().any(|name| name.eq_ignore_ascii_case(field))
+    }
+
+    fn redact(&self, fields: &mut Map<String, Value>) {
+        for (name, value) in fields.iter_mut() {
+            if self.is_redacted(name) {
+                *value = REDACTED.into();
+            }
+        }
+    }
+}
+
+impl<S> FormatEvent<S, JsonFields> for LambdaJsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+
+        let mut invocation = LogLine::default();
+        let mut spans = Vec::new();
+        for span in ctx.event_scope().into_iter().flat_map(|scope| scope.from_root()) {
+            let extensions = span.extensions();
+            let mut fields = extensions
+                .get::<FormattedFields<JsonFields>>()
+                .and_then(|fields| serde_json::from_str::<Map<String, Value>>(fields).ok())
+                .unwrap_or_default();
+            for (field, key) in INVOCATION_FIELDS {
+                if let Some(value) = fields.remove(field) {
+                    invocation.insert(key, value);
+                }
+            }
+            self.redact(&mut fields);
+
+            let mut span_value = Map::new();
+            span_value.insert("name".into(), span.name().into());
+            span_value.extend(fields);
+            spans.push(Value::Object(span_value));
+        }
+
+        let mut visitor = FieldVisitor {
+            format: self,
+            fields: Map::new(),
+        };
+        event.record(&mut visitor);
+        let mut fields = visitor.fields;
+
+        let mut line = LogLine::default();
+        line.insert("timestamp", timestamp.into());
+        line.insert("level", event.metadata().level().as_str().into());
+        line.insert("requestId", invocation.remove("requestId"));
+        line.insert("message", fields.remove("message").unwrap_or_default());
+        for (key, value) in invocation.0 {
+            line.insert(&key, value);
+        }
+        for (name, value) in fields {
+            line.insert_field(name, value);
+        }
+        if !spans.is_empty() {
+            line.insert("spans", Value::Array(spans));
+        }
+
+        writeln!(writer, "{}", line.into_value())
+    }
+}
+
+/// Keys of a log line, in the order in which they are written.
+#[derive(Default)]
+struct LogLine(Vec<(String, Value)>);
+
+impl LogLine {
+    /// Insert a key, or replace the value of an existing key in its original position.
+    /// Null values are skipped.
+    fn insert(&mut self, key: &str, value: Value) {
+        if value.is_null() {
+            return;
+        }
+        match self.0.iter_mut().find(|(name, _)| name == key) {
+            Some((_, current)) => *current = value,
+            None => self.0.push((key.to_owned(), value)),
+        }
+    }
+
+    /// Insert a field of the event. Fields can't replace the timestamp and the level of the line.
+    fn insert_field(&mut self, key: String, value: Value) {
+        if key != "timestamp" && key != "level" {
+            self.insert(&key, value);
+        }
+    }
+
+    fn remove(&mut self, key: &str) -> Value {
+        match self.0.iter().position(|(name, _)| name == key) {
+            Some(index) => self.0.remove(index).1,
+            None => Value::Null,
+        }
+    }
+
+    fn into_value(self) -> LogLineValue {
+        LogLineValue(self.0)
+    }
+}
+
+/// Serialize the keys of a log line as a JSON object that preserves their order.
+struct LogLineValue(Vec<(String, Value)>);
+
+impl fmt::Display for LogLineValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('{')?;
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_char(',')?;
+            }
+            write!(f, "{}:{}", Value::from(key.as_str()), value)?;
+        }
+        f.write_char('}')
+    }
+}
+
+struct FieldVisitor<'a> {
+    format: &'a LambdaJsonFormat,
+    fields: Map<String, Value>,
+}
+
+impl FieldVisitor<'_> {
+    fn record(&mut self, field: &Field, value: Value) {
+        let value = if self.format.is_redacted(field.name()) {
+            REDACTED.into()
+        } else {
+            value
+        };
+        self.fields.insert(field.name().to_owned(), value);
+    }
+}
+
+impl Visit for FieldVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.into());
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.record(field, value.to_string().into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, format!("{value:?}").into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::fmt::MakeWriter;
+
+    #[derive(Clone, Default)]
+    struct Lines(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Lines {
+        type Writer = Lines;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn log_lines(format: LambdaJsonFormat, f: impl FnOnce()) -> Vec<String> {
+        let lines = Lines::default();
+        let subscriber = tracing_subscriber::fmt()
+            .fmt_fields(JsonFields::new())
+            .event_format(format)
+            .with_writer(lines.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+
+        let output = String::from_utf8(lines.0.lock().unwrap().clone()).unwrap();
+        output.lines().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn log_line_schema() {
+        let format = LambdaJsonFormat::new().with_redacted_fields(["Password"]);
+        let lines = log_lines(format, || {
+            let span = tracing::info_span!(
+                "Lambda runtime invoke",
+                requestId = "req-1",
+                xrayTraceId = "Root=1-abc",
+                functionArn = "arn:aws:lambda:us-east-1:123456789012:function:test",
+                "faas.coldstart" = true
+            );
+            let _guard = span.enter();
+            let inner = tracing::info_span!("load_order", order_id = 42, password = "hunter2");
+            let _inner = inner.enter();
+            tracing::warn!(password = "hunter2", attempts = 3, "order not found");
+        });
+        assert_eq!(lines.len(), 1);
+
+        let line = &lines[0];
+        let positions: Vec<usize> = [
+            "timestamp",
+            "level",
+            "requestId",
+            "message",
+            "xrayTraceId",
+            "functionArn",
+        ]
+        .into_iter()
+        .map(|key| line.find(&format!("\"{key}\"")).unwrap())
+        .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]), "{line}");
+
+        let mut value: Value = serde_json::from_str(line).unwrap();
+        assert!(value["timestamp"].as_str().unwrap().ends_with('Z'));
+        value.as_object_mut().unwrap().remove("timestamp");
+        let expected = serde_json::json!({
+            "level": "WARN",
+            "requestId": "req-1",
+            "message": "order not found",
+            "xrayTraceId": "Root=1-abc",
+            "functionArn": "arn:aws:lambda:us-east-1:123456789012:function:test",
+            "coldStart": true,
+            "password": "[REDACTED]",
+            "attempts": 3,
+            "spans": [
+                {"name": "Lambda runtime invoke"},
+                {"name": "load_order", "order_id": 42, "password": "[REDACTED]"},
+            ],
+        });
+        assert_eq!(expected, value);
+    }
+
+    #[test]
+    fn log_line_outside_of_spans() {
+        let lines = log_lines(LambdaJsonFormat::new(), || {
+            tracing::info!(ready = true, "runtime started")
+        });
+        let mut value: Value = serde_json::from_str(&lines[0]).unwrap();
+        value.as_object_mut().unwrap().remove("timestamp");
+        assert_eq!(
+            serde_json::json!({"level": "INFO", "message": "runtime started", "ready": true}),
+            value
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::{Arc, Mutex, OnceLock};
+use tracing_subscriber::{
+    filter::{EnvFilter, LevelFilter},
+    reload, Registry,
+};
+
+static LOG_LEVEL_HANDLE: OnceLock<LogLevelHandle> = OnceLock::new();
+
+/// Handle to change the log level of the default subscriber while the function is running.
+///
+/// The handle is available with [log_level_handle] after initializing the subscriber with
+/// [init_default_subscriber](super::init_default_subscriber) or
+/// [init_default_subscriber_with_writer](super::init_default_subscriber_with_writer).
+#[derive(Clone, Debug)]
+pub struct LogLevelHandle {
+    inner: Arc<HandleInner>,
+}
+
+#[derive(Debug)]
+struct HandleInner {
+    filter: reload::Handle<EnvFilter, Registry>,
+    level: LevelFilter,
+    /// Number of [LogLevelGuard] alive. The default level is restored when it drops to zero.
+    raised: Mutex<usize>,
+}
+
+/// Get the handle to change the log level of the default subscriber.
+/// Returns `None` when the default subscriber has not been initialized.
+pub fn log_level_handle() -> Option<LogLevelHandle> {
+    LOG_LEVEL_HANDLE.get().cloned()
+}
+
+impl LogLevelHandle {
+    /// Wrap the filter of the default subscriber, and make the handle available with [log_level_handle].
+    pub(crate) fn register(filter: reload::Handle<EnvFilter, Registry>, level: LevelFilter) {
+        let handle = LogLevelHandle {
+            inner: Arc::new(HandleInner {
+                filter,
+                level,
+                raised: Mutex::new(0),
+            }),
+        };
+        // The subscriber can only be initialized once, so the handle is only registered once.
+        let _ = LOG_LEVEL_HANDLE.set(handle);
+    }
+
+    /// The default log level of the subscriber.
+    pub fn level(&self) -> LevelFilter {
+        self.inner.level
+    }
+
+    /// Raise the log level to `DEBUG` until the returned guard is dropped.
+    ///
+    /// The log level is global, so it's raised for all the invocations in flight.
+    /// When several guards are alive, the default level is restored after the last one is dropped.
+    /// The level is never lowered, if the default level is already `DEBUG` or `TRACE` nothing changes.
+    pub fn raise_to_debug(&self) -> LogLevelGuard {
+        let mut raised = self.inner.raised.lock().unwrap_or_else(|err| err.into_inner());
+        if *raised == 0 && self.inner.level < LevelFilter::DEBUG {
+            self.reload(LevelFilter::DEBUG);
+        }
+        *raised += 1;
+        LogLevelGuard { handle: self.clone() }
+    }
+
+    fn reload(&self, level: LevelFilter) {
+        if let Err(err) = self.inner.filter.reload(env_filter(level)) {
+            tracing::warn!(error = %err, "failed to change the log level");
+        }
+    }
+}
+
+/// Guard returned by [LogLevelHandle::raise_to_debug].
+/// The default log level is restored when the last guard is dropped.
+#[derive(Debug)]
+pub struct LogLevelGuard {
+    handle: LogLevelHandle,
+}
+
+impl Drop for LogLevelGuard {
+    fn drop(&mut self) {
+        let inner = &self.handle.inner;
+        let mut raised = inner.raised.lock().unwrap_or_else(|err| err.into_inner());
+        *raised -= 1;
+        if *raised == 0 && inner.level < LevelFilter::DEBUG {
+            self.handle.reload(inner.level);
+        }
+    }
+}
+
+/// Build the filter of the default subscriber. Directives in `RUST_LOG` apply on top of the default level.
+pub(crate) fn env_filter(level: LevelFilter) -> EnvFilter {
+    EnvFilter::builder()
+        .with_default_directive(level.into())
+        .from_env_lossy()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn raise_and_restore_level() {
+        let (filter, reload_handle) = reload::Layer::new(EnvFilter::new("info"));
+        let subscriber = tracing_subscriber::registry().with(filter);
+        let handle = LogLevelHandle {
+            inner: Arc::new(HandleInner {
+                filter: reload_handle,
+                level: LevelFilter::INFO,
+                raised: Mutex::new(0),
+            }),
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert!(!tracing::enabled!(tracing::Level::DEBUG));
+            let first = handle.raise_to_debug();
+            let second = handle.raise_to_debug();
+            assert!(tracing::enabled!(tracing::Level::DEBUG));
+            drop(first);
+            assert!(tracing::enabled!(tracing::Level::DEBUG));
+            drop(second);
+            assert!(!tracing::enabled!(tracing::Level::DEBUG));
+            assert!(tracing::enabled!(tracing::Level::INFO));
+        });
+    }
+}
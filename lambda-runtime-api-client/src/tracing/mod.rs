@@ -7,17 +7,44 @@
 
 use std::{env, str::FromStr};
 
-use subscriber::filter::{EnvFilter, LevelFilter};
+use subscriber::{filter::LevelFilter, layer::SubscriberExt, reload, util::SubscriberInitExt};
+
+mod json;
+mod level;
+pub use json::LambdaJsonFormat;
+pub use level::{log_level_handle, LogLevelGuard, LogLevelHandle};
 /// Re-export the `tracing` crate to have access to tracing macros
 /// like `info!`, `debug!`, `trace!` and so on.
 pub use tracing::*;
 
 /// Re-export the `tracing-subscriber` crate to build your own subscribers.
 pub use tracing_subscriber as subscriber;
-use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::{format::JsonFields, MakeWriter};
 
 const DEFAULT_LOG_LEVEL: &str = "INFO";
 
+/// Format of the log lines written by the function, configured with
+/// [Lambda's advanced logging controls](https://docs.aws.amazon.com/lambda/latest/dg/monitoring-cloudwatchlogs-advanced.html).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LogFormat {
+    /// Plain text log lines. This is the default format.
+    #[default]
+    Text,
+    /// Structured log lines, formatted as JSON objects.
+    Json,
+}
+
+impl LogFormat {
+    /// Read the log format from the `AWS_LAMBDA_LOG_FORMAT` environment variable.
+    /// Any value other than `JSON`, case insensitive, is considered plain text.
+    pub fn from_env() -> Self {
+        match env::var("AWS_LAMBDA_LOG_FORMAT") {
+            Ok(format) if format.eq_ignore_ascii_case("json") => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
 /// Initialize `tracing-subscriber` with default logging options.
 ///
 /// The default subscriber writes logs to STDOUT in the current context.
@@ -32,8 +59,8 @@ const DEFAULT_LOG_LEVEL: &str = "INFO";
 ///     - if none of those two variables are set, use `INFO` as the logging level.
 ///
 /// The logging format can also be changed based on Lambda's advanced logging controls.
-/// If the `AWS_LAMBDA_LOG_FORMAT` environment variable is set to `JSON`, the log lines will be formatted as json objects,
-/// otherwise they will be formatted with the default tracing format.
+/// If the `AWS_LAMBDA_LOG_FORMAT` environment variable is set to `JSON`, the log lines will be formatted as json objects
+/// with [LambdaJsonFormat], otherwise they will be formatted with the default tracing format.
 pub fn init_default_subscriber() {
     init_default_subscriber_with_writer(std::io::stdout);
 }
@@ -55,30 +82,36 @@ pub fn init_default_subscriber() {
 ///     - if none of those two variables are set, use `INFO` as the logging level.
 ///
 /// The logging format can also be changed based on Lambda's advanced logging controls.
-/// If the `AWS_LAMBDA_LOG_FORMAT` environment variable is set to `JSON`, the log lines will be formatted as json objects,
-/// otherwise they will be formatted with the default tracing format.
+/// If the `AWS_LAMBDA_LOG_FORMAT` environment variable is set to `JSON`, the log lines will be formatted as json objects
+/// with [LambdaJsonFormat], otherwise they will be formatted with the default tracing format.
+///
+/// The log level can be changed while the function is running with the handle returned by [log_level_handle].
 pub fn init_default_subscriber_with_writer<Writer>(writer: Writer)
 where
     Writer: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
 {
-    let log_format = env::var("AWS_LAMBDA_LOG_FORMAT").unwrap_or_default();
     let log_level_str = env::var("AWS_LAMBDA_LOG_LEVEL").or_else(|_| env::var("RUST_LOG"));
     let log_level =
         LevelFilter::from_str(log_level_str.as_deref().unwrap_or(DEFAULT_LOG_LEVEL)).unwrap_or(LevelFilter::INFO);
 
-    let collector = tracing_subscriber::fmt()
+    let (filter, filter_handle) = reload::Layer::new(level::env_filter(log_level));
+    level::LogLevelHandle::register(filter_handle, log_level);
+
+    let fmt_layer = tracing_subscriber::fmt::layer()
         .with_target(false)
         .without_time()
-        .with_env_filter(
-            EnvFilter::builder()
-                .with_default_directive(log_level.into())
-                .from_env_lossy(),
-        )
         .with_writer(writer);
+    let collector = tracing_subscriber::registry().with(filter);
 
-    if log_format.eq_ignore_ascii_case("json") {
-        collector.json().init()
+    if LogFormat::from_env() == LogFormat::Json {
+        collector
+            .with(
+                fmt_layer
+                    .fmt_fields(JsonFields::new())
+                    .event_format(LambdaJsonFormat::new()),
+            )
+            .init()
     } else {
-        collector.init()
+        collector.with(fmt_layer).init()
     }
 }
@@ -0,0 +1,360 @@
+#![deny(clippy::all, clippy::cargo)]
+#![warn(missing_docs, nonstandard_style, rust_2018_idioms)]
+#![allow(clippy::multiple_crate_versions)]
+
+//! Attribute macros to declare the entry point of AWS Lambda functions.
+//!
+//! Don't depend on this crate directly, use the macros re-exported as
+//! `lambda_runtime::main` and `lambda_http::main` instead.
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, ToTokens};
+use syn::{meta::ParseNestedMeta, parse::Parser, Error, FnArg, ItemFn, LitBool, LitStr, Path, Result, Type};
+
+/// Generate the `main` function of a Lambda function that runs the annotated handler.
+/// See `lambda_runtime::main` for the documentation.
+#[proc_macro_attribute]
+pub fn main(args: proc_macro::TokenStream, item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand(Target::Runtime, args.into(), item.into())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Generate the `main` function of a Lambda function that runs the annotated HTTP handler.
+/// See `lambda_http::main` for the documentation.
+#[proc_macro_attribute]
+pub fn http_main(args: proc_macro::TokenStream, item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand(Target::Http, args.into(), item.into())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Crate that runs the handler.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    /// `lambda_runtime`, the handler takes extractors, see `lambda_runtime::extract`.
+    Runtime,
+    /// `lambda_http`, the handler takes a `Request`, and the `State` extractor.
+    Http,
+}
+
+/// Tokio runtime used to run the function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Flavor {
+    CurrentThread,
+    MultiThread,
+}
+
+/// Arguments of the attribute.
+struct Args {
+    init: Option<Path>,
+    graceful_shutdown: Option<Option<Path>>,
+    tracing: bool,
+    flavor: Flavor,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            init: None,
+            graceful_shutdown: None,
+            tracing: true,
+            flavor: Flavor::CurrentThread,
+        }
+    }
+}
+
+impl Args {
+    fn parse(args: TokenStream) -> Result<Self> {
+        let mut parsed = Args::default();
+        let parser = syn::meta::parser(|meta| parsed.parse_meta(meta));
+        parser.parse2(args)?;
+        Ok(parsed)
+    }
+
+    fn parse_meta(&mut self, meta: ParseNestedMeta<'_>) -> Result<()> {
+        if meta.path.is_ident("init") {
+            self.init = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("graceful_shutdown") {
+            let hook = match meta.input.peek(syn::Token![=]) {
+                true => Some(meta.value()?.parse()?),
+                false => None,
+            };
+            self.graceful_shutdown = Some(hook);
+        } else if meta.path.is_ident("tracing") {
+            self.tracing = meta.value()?.parse::<LitBool>()?.value;
+        } else if meta.path.is_ident("flavor") {
+            let flavor: LitStr = meta.value()?.parse()?;
+            self.flavor = match flavor.value().as_str() {
+                "current_thread" => Flavor::CurrentThread,
+                "multi_thread" => Flavor::MultiThread,
+                _ => {
+                    return Err(Error::new_spanned(
+                        flavor,
+                        "unknown flavor, expected `current_thread` or `multi_thread`",
+                    ))
+                }
+            };
+        } else {
+            return Err(meta.error("unknown argument, expected `init`, `graceful_shutdown`, `tracing`, or `flavor`"));
+        }
+        Ok(())
+    }
+}
+
+fn expand(target: Target, args: TokenStream, item: TokenStream) -> Result<TokenStream> {
+    let args = Args::parse(args)?;
+    let handler: ItemFn = syn::parse2(item)?;
+    if handler.sig.asyncness.is_none() {
+        return Err(Error::new_spanned(
+            handler.sig.fn_token,
+            "the Lambda handler must be an `async fn`",
+        ));
+    }
+    if handler.sig.ident == "main" {
+        return Err(Error::new_spanned(
+            &handler.sig.ident,
+            "annotate the Lambda handler, the `main` function is generated by the attribute",
+        ));
+    }
+
+    let name = &handler.sig.ident;
+    let runtime = match target {
+        Target::Runtime => quote!(::lambda_runtime),
+        Target::Http => quote!(::lambda_http::lambda_runtime),
+    };
+
+    let init_tracing = args
+        .tracing
+        .then(|| quote!(#runtime::__private::init_default_subscriber();));
+
+    let graceful_shutdown = args.graceful_shutdown.as_ref().map(|hook| {
+        let hook = match hook {
+            Some(hook) => hook.to_token_stream(),
+            None => quote!(|| async {}),
+        };
+        quote!(#runtime::spawn_graceful_shutdown_handler(#hook).await;)
+    });
+
+    let run = match (target, &args.init) {
+        (Target::Runtime, None) => quote! {
+            #runtime::run(#runtime::extract::handler(#name)).await
+        },
+        (Target::Runtime, Some(init)) => quote! {
+            #runtime::run_with_init(#init(), |state| #runtime::extract::handler(#name).with_state(state)).await
+        },
+        (Target::Http, None) => {
+            let args = http_handler_args(&handler, &runtime, false)?;
+            quote! {
+                ::lambda_http::run(::lambda_http::service_fn(move |request| #name(#(#args),*))).await
+            }
+        }
+        (Target::Http, Some(init)) => {
+            let args = http_handler_args(&handler, &runtime, true)?;
+            quote! {
+                let ::core::option::Option::Some(state) = #runtime::__private::init(#init()).await? else {
+                    return ::core::result::Result::Ok(());
+                };
+                ::lambda_http::run(::lambda_http::service_fn(move |request| #name(#(#args),*))).await
+            }
+        }
+    };
+
+    // Current-thread runtimes run the handler in a `LocalSet`, like `lambda_runtime::run_local`,
+    // so the handler future doesn't need to be `Send`.
+    let block_on = match args.flavor {
+        Flavor::CurrentThread => quote! {
+            let runtime = #runtime::__private::tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            #runtime::__private::tokio::task::LocalSet::new().block_on(&runtime, body)
+        },
+        Flavor::MultiThread => quote! {
+            #runtime::__private::tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?
+                .block_on(body)
+        },
+    };
+    let main = syn::Ident::new("main", Span::call_site());
+
+    Ok(quote! {
+        #handler
+
+        fn #main() -> ::core::result::Result<(), #runtime::Error> {
+            let body = async {
+                #init_tracing
+                #graceful_shutdown
+                #run
+            };
+            #block_on
+        }
+    })
+}
+
+/// Arguments to call an HTTP handler with: the request, and the state of the `init` function
+/// for the arguments of type `State`, like the `State` extractor of `lambda_runtime` handlers.
+fn http_handler_args(handler: &ItemFn, runtime: &TokenStream, has_state: bool) -> Result<Vec<TokenStream>> {
+    let mut has_request = false;
+    let args = handler
+        .sig
+        .inputs
+        .iter()
+        .map(|input| {
+            let ty = match input {
+                FnArg::Typed(arg) => &arg.ty,
+                FnArg::Receiver(receiver) => {
+                    return Err(Error::new_spanned(receiver, "the Lambda handler can't take `self`"))
+                }
+            };
+            if is_state(ty) {
+                if !has_state {
+                    return Err(Error::new_spanned(
+                        ty,
+                        "the `State` extractor requires the `init` argument of the attribute",
+                    ));
+                }
+                Ok(quote!(#runtime::extract::State(::core::clone::Clone::clone(&state))))
+            } else if !has_request {
+                has_request = true;
+                Ok(quote!(request))
+            } else {
+                Err(Error::new_spanned(
+                    ty,
+                    "the HTTP handler takes the `Request`, and optionally the `State` of the `init` function",
+                ))
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if !has_request {
+        return Err(Error::new_spanned(
+            &handler.sig.inputs,
+            "the HTTP handler must take the `Request` as argument",
+        ));
+    }
+    Ok(args)
+}
+
+/// Whether the type is the `State` extractor, as `State<T>` or with a path like `extract::State<T>`.
+fn is_state(ty: &Type) -> bool {
+    match ty {
+        Type::Path(ty) => ty.qself.is_none() && ty.path.segments.last().is_some_and(|segment| segment.ident == "State"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_to_string(target: Target, args: TokenStream, item: TokenStream) -> String {
+        expand(target, args, item).unwrap().to_string()
+    }
+
+    #[test]
+    fn parse_args() {
+        let args = Args::parse(quote!(init = config::load, graceful_shutdown, tracing = false)).unwrap();
+        assert_eq!(args.init.unwrap().to_token_stream().to_string(), "config :: load");
+        assert!(matches!(args.graceful_shutdown, Some(None)));
+        assert!(!args.tracing);
+        assert_eq!(args.flavor, Flavor::CurrentThread);
+
+        let args = Args::parse(quote!(graceful_shutdown = flush, flavor = "multi_thread")).unwrap();
+        assert_eq!(
+            args.graceful_shutdown.unwrap().unwrap().to_token_stream().to_string(),
+            "flush"
+        );
+        assert_eq!(args.flavor, Flavor::MultiThread);
+
+        assert!(Args::parse(quote!(flavor = "local")).is_err());
+        assert!(Args::parse(quote!(workers = 2)).is_err());
+    }
+
+    #[test]
+    fn expand_runtime_main() {
+        let item = quote!(
+            async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+                Ok(event.payload)
+            }
+        );
+        let expanded = expand_to_string(Target::Runtime, quote!(), item.clone());
+        assert!(expanded.contains("async fn func"));
+        assert!(expanded.contains(":: lambda_runtime :: __private :: init_default_subscriber ()"));
+        assert!(expanded.contains(":: lambda_runtime :: run (:: lambda_runtime :: extract :: handler (func))"));
+        assert!(expanded.contains("new_current_thread"));
+        assert!(expanded.contains("LocalSet :: new () . block_on (& runtime , body)"));
+        assert!(!expanded.contains("spawn_graceful_shutdown_handler"));
+
+        let expanded = expand_to_string(Target::Runtime, quote!(init = load, graceful_shutdown), item);
+        assert!(expanded.contains("run_with_init (load () , | state |"));
+        assert!(expanded.contains("with_state (state)"));
+        assert!(expanded.contains("spawn_graceful_shutdown_handler (|| async { }) . await"));
+    }
+
+    #[test]
+    fn expand_http_main() {
+        let item = quote!(
+            async fn func(request: Request) -> Result<Response<Body>, Error> {
+                Ok(Response::default())
+            }
+        );
+        let expanded = expand_to_string(Target::Http, quote!(), item);
+        assert!(
+            expanded.contains(":: lambda_http :: run (:: lambda_http :: service_fn (move | request | func (request)))")
+        );
+
+        let item = quote!(
+            async fn func(State(config): State<Arc<Config>>, request: Request) -> Result<Response<Body>, Error> {
+                Ok(Response::default())
+            }
+        );
+        let expanded = expand_to_string(Target::Http, quote!(init = load, tracing = false), item);
+        assert!(expanded.contains(":: lambda_http :: lambda_runtime :: __private :: init (load ()) . await ?"));
+        assert!(expanded.contains(
+            "func (:: lambda_http :: lambda_runtime :: extract :: State (:: core :: clone :: Clone :: clone (& state)) , request)"
+        ));
+        assert!(!expanded.contains("init_default_subscriber"));
+    }
+
+    #[test]
+    fn invalid_http_handlers() {
+        let item = quote!(
+            async fn func(State(config): State<Arc<Config>>, request: Request) -> Result<Response<Body>, Error> {
+                Ok(Response::default())
+            }
+        );
+        let err = expand(Target::Http, quote!(), item).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("the `State` extractor requires the `init` argument"));
+
+        let item = quote!(
+            async fn func(config: Arc<Config>, request: Request) -> Result<Response<Body>, Error> {
+                Ok(Response::default())
+            }
+        );
+        let err = expand(Target::Http, quote!(init = load), item).unwrap_err();
+        assert!(err.to_string().starts_with("the HTTP handler takes the `Request`"));
+    }
+
+    #[test]
+    fn invalid_handlers() {
+        let err = expand(
+            Target::Runtime,
+            quote!(),
+            quote!(
+                fn func() {}
+            ),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "the Lambda handler must be an `async fn`");
+        let err = expand(
+            Target::Runtime,
+            quote!(),
+            quote!(
+                async fn main() {}
+            ),
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("annotate the Lambda handler"));
+    }
+}
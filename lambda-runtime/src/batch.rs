@@ -0,0 +1,445 @@
+//! Process the records of SQS, Kinesis, and DynamoDB batches one by one, and report
+//! the records that failed with a [partial batch response](https://docs.aws.amazon.com/lambda/latest/dg/services-sqs-errorhandling.html#services-sqs-batchfailurereporting).
+//!
+//! A [BatchProcessor] calls a handler function for every record of the batch, and builds the
+//! response with the identifiers of the records that failed, so that only those are retried.
+//! The event source mapping of the function must be configured with the `ReportBatchItemFailures`
+//! response type, otherwise Lambda ignores the response and considers the whole batch processed.
+//!
+//! The records of ordered sources, Kinesis streams, DynamoDB streams, and SQS FIFO queues, are
+//! processed sequentially, and the processing stops at the first failure: the failed record and
+//! all the records after it are reported as failed, so they are retried in order. The records
+//! of standard SQS queues can be processed concurrently with [BatchProcessor::with_concurrency].
+//!
+//! # Example
+//! ```no_run
+//! use aws_lambda_events::sqs::{SqsEvent, SqsMessage};
+//! use lambda_runtime::{batch::BatchProcessor, Error};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let processor = BatchProcessor::new(|message: SqsMessage| async move {
+//!         let body = message.body.ok_or("message without body")?;
+//!         println!("processing {body}");
+//!         Ok::<_, Error>(())
+//!     })
+//!     .with_concurrency(10);
+//!     lambda_runtime::run(processor.for_event::<SqsEvent>()).await
+//! }
+//! ```
+use crate::{Diagnostic, LambdaEvent};
+use aws_lambda_events::{
+    dynamodb,
+    kinesis::{KinesisEvent, KinesisEventRecord},
+    sqs::{SqsEvent, SqsEventObj, SqsMessage, SqsMessageObj},
+    streams::{
+        DynamoDbBatchItemFailure, DynamoDbEventResponse, KinesisBatchItemFailure, KinesisEventResponse,
+        SqsBatchItemFailure, SqsEventResponse,
+    },
+};
+use futures::{future::BoxFuture, stream, StreamExt};
+use serde::Serialize;
+use std::{future::Future, marker::PhantomData, sync::Arc, task};
+use tower::Service;
+use tracing::{error, trace};
+
+/// Error type reported to Lambda when a failed record doesn't have an identifier.
+const MISSING_IDENTIFIER_ERROR_TYPE: &str = "Runtime.BatchItemWithoutIdentifier";
+
+/// Batch events that can report partial failures.
+///
+/// It's implemented for the SQS, Kinesis, and DynamoDB events of [aws_lambda_events].
+pub trait BatchEvent {
+    /// Type of the records of the batch.
+    type Record;
+    /// Type of the partial batch response.
+    type Response;
+
+    /// Whether the records must be processed in order.
+    fn is_ordered(&self) -> bool;
+
+    /// Take the records of the batch.
+    fn into_records(self) -> Vec<Self::Record>;
+
+    /// Identifier of a record, reported to Lambda when the record fails.
+    fn item_identifier(record: &Self::Record) -> Option<&str>;
+
+    /// Build the response from the identifiers of the records that failed.
+    fn response(failures: Vec<String>) -> Self::Response;
+}
+
+fn is_fifo_queue(event_source_arn: Option<&String>) -> bool {
+    event_source_arn.is_some_and(|arn| arn.ends_with(".fifo"))
+}
+
+// The responses have an extra field when the `catch-all-fields` feature of aws_lambda_events is enabled.
+#[allow(clippy::needless_update)]
+fn sqs_response(failures: Vec<String>) -> SqsEventResponse {
+    SqsEventResponse {
+        batch_item_failures: failures
+            .into_iter()
+            .map(|id| SqsBatchItemFailure {
+                item_identifier: Some(id),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+impl BatchEvent for SqsEvent {
+    type Record = SqsMessage;
+    type Response = SqsEventResponse;
+
+    fn is_ordered(&self) -> bool {
+        self.records
+            .first()
+            .is_some_and(|message| is_fifo_queue(message.event_source_arn.as_ref()))
+    }
+
+    fn into_records(self) -> Vec<Self::Record> {
+        self.records
+    }
+
+    fn item_identifier(record: &Self::Record) -> Option<&str> {
+        record.message_id.as_deref()
+    }
+
+    fn response(failures: Vec<String>) -> Self::Response {
+        sqs_response(failures)
+    }
+}
+
+impl<T: Serialize> BatchEvent for SqsEventObj<T> {
+    type Record = SqsMessageObj<T>;
+    type Response = SqsEventResponse;
+
+    fn is_ordered(&self) -> bool {
+        self.records
+            .first()
+            .is_some_and(|message| is_fifo_queue(message.event_source_arn.as_ref()))
+    }
+
+    fn into_records(self) -> Vec<Self::Record> {
+        self.records
+    }
+
+    fn item_identifier(record: &Self::Record) -> Option<&str> {
+        record.message_id.as_deref()
+    }
+
+    fn response(failures: Vec<String>) -> Self::Response {
+        sqs_response(failures)
+    }
+}
+
+impl BatchEvent for KinesisEvent {
+    type Record = KinesisEventRecord;
+    type Response = KinesisEventResponse;
+
+    fn is_ordered(&self) -> bool {
+        true
+    }
+
+    fn into_records(self) -> Vec<Self::Record> {
+        self.records
+    }
+
+    fn item_identifier(record: &Self::Record) -> Option<&str> {
+        Some(&record.kinesis.sequence_number)
+    }
+
+    #[allow(clippy::needless_update)]
+    fn response(failures: Vec<String>) -> Self::Response {
+        KinesisEventResponse {
+            batch_item_failures: failures
+                .into_iter()
+                .map(|id| KinesisBatchItemFailure {
+                    item_identifier: Some(id),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl BatchEvent for dynamodb::Event {
+    type Record = dynamodb::EventRecord;
+    type Response = DynamoDbEventResponse;
+
+    fn is_ordered(&self) -> bool {
+        true
+    }
+
+    fn into_records(self) -> Vec<Self::Record> {
+        self.records
+    }
+
+    fn item_identifier(record: &Self::Record) -> Option<&str> {
+        record.change.sequence_number.as_deref()
+    }
+
+    #[allow(clippy::needless_update)]
+    fn response(failures: Vec<String>) -> Self::Response {
+        DynamoDbEventResponse {
+            batch_item_failures: failures
+                .into_iter()
+                .map(|id| DynamoDbBatchItemFailure {
+                    item_identifier: Some(id),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Calls a handler function for every record of a batch, and reports the records that failed.
+/// See the [module documentation](self) for more details.
+pub struct BatchProcessor<F> {
+    handler: Arc<F>,
+    concurrency: usize,
+}
+
+impl<F> Clone for BatchProcessor<F> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+            concurrency: self.concurrency,
+        }
+    }
+}
+
+impl<F> BatchProcessor<F> {
+    /// Create a new processor that calls `handler` for every record, one at a time.
+    pub fn new(handler: F) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            concurrency: 1,
+        }
+    }
+
+    /// Set the maximum number of records of unordered sources processed at the same time.
+    /// The records of ordered sources are always processed one at a time. A value of `0`
+    /// is treated as `1`.
+    pub fn with_concurrency(self, concurrency: usize) -> Self {
+        Self {
+            concurrency: concurrency.max(1),
+            ..self
+        }
+    }
+
+    /// Create a service that processes the batches of type `E`, for [run](crate::run).
+    pub fn for_event<E>(self) -> BatchService<F, E> {
+        BatchService {
+            processor: self,
+            _event: PhantomData,
+        }
+    }
+
+    /// Process the records of a batch, and build the response with the records that failed.
+    ///
+    /// It fails when a record that failed doesn't have an identifier, since Lambda
+    /// can't retry it alone. The whole batch is retried in that case.
+    pub async fn process<E, Fut, Err>(&self, event: E) -> Result<E::Response, Diagnostic>
+    where
+        E: BatchEvent,
+        F: Fn(E::Record) -> Fut,
+        Fut: Future<Output = Result<(), Err>>,
+        Err: Into<Diagnostic>,
+    {
+        let ordered = event.is_ordered();
+        let records: Vec<_> = event
+            .into_records()
+            .into_iter()
+            .map(|record| (E::item_identifier(&record).map(String::from), record))
+            .collect();
+        trace!(records = records.len(), ordered, "processing batch");
+
+        let mut failures = Vec::new();
+        if ordered || self.concurrency == 1 {
+            let mut records = records.into_iter();
+            while let Some((id, record)) = records.next() {
+                if let Err(err) = (self.handler)(record).await {
+                    failures.push(failed_record(id, err.into())?);
+                    if ordered {
+                        // Lambda retries ordered sources from the first failure, skip the remaining records.
+                        for (id, _) in records {
+                            failures.push(id.ok_or_else(missing_identifier)?);
+                        }
+                        break;
+                    }
+                }
+            }
+        } else {
+            let results: Vec<_> = stream::iter(records)
+                .map(|(id, record)| async move { (id, (self.handler)(record).await.map_err(Into::into)) })
+                .buffer_unordered(self.concurrency)
+                .collect()
+                .await;
+            for (id, result) in results {
+                if let Err(diagnostic) = result {
+                    failures.push(failed_record(id, diagnostic)?);
+                }
+            }
+        }
+        Ok(E::response(failures))
+    }
+}
+
+fn failed_record(id: Option<String>, diagnostic: Diagnostic) -> Result<String, Diagnostic> {
+    error!(
+        item_identifier = id.as_deref(),
+        error_type = diagnostic.error_type,
+        error_message = diagnostic.error_message,
+        "failed to process batch record"
+    );
+    id.ok_or_else(missing_identifier)
+}
+
+fn missing_identifier() -> Diagnostic {
+    Diagnostic {
+        error_type: MISSING_IDENTIFIER_ERROR_TYPE.into(),
+        error_message: "A record without identifier failed, the whole batch must be retried".into(),
+        ..Default::default()
+    }
+}
+
+/// Tower service returned by [BatchProcessor::for_event].
+pub struct BatchService<F, E> {
+    processor: BatchProcessor<F>,
+    _event: PhantomData<fn(E)>,
+}
+
+impl<F, E> Clone for BatchService<F, E> {
+    fn clone(&self) -> Self {
+        Self {
+            processor: self.processor.clone(),
+            _event: PhantomData,
+        }
+    }
+}
+
+impl<F, E, Fut, Err> Service<LambdaEvent<E>> for BatchService<F, E>
+where
+    E: BatchEvent + Send + 'static,
+    E::Record: Send,
+    E::Response: Send,
+    F: Fn(E::Record) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), Err>> + Send,
+    Err: Into<Diagnostic>,
+{
+    type Response = E::Response;
+    type Error = Diagnostic;
+    type Future = BoxFuture<'static, Result<E::Response, Diagnostic>>;
+
+    fn poll_ready(&mut self, _cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: LambdaEvent<E>) -> Self::Future {
+        let processor = self.processor.clone();
+        Box::pin(async move { processor.process(req.payload).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    fn sqs_event(arn: &str, bodies: &[&str]) -> SqsEvent {
+        let records: Vec<Value> = bodies
+            .iter()
+            .enumerate()
+            .map(|(i, body)| json!({ "messageId": format!("m{i}"), "body": body, "eventSourceARN": arn }))
+            .collect();
+        serde_json::from_value(json!({ "Records": records })).unwrap()
+    }
+
+    fn failures(response: SqsEventResponse) -> Vec<String> {
+        let mut ids: Vec<_> = response
+            .batch_item_failures
+            .into_iter()
+            .map(|failure| failure.item_identifier.unwrap())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    async fn fail_on_bad(message: SqsMessage) -> Result<(), Error> {
+        match message.body.as_deref() {
+            Some("bad") => Err("bad record".into()),
+            _ => Ok(()),
+        }
+    }
+
+    #[tokio::test]
+    async fn standard_queue_reports_every_failure() {
+        let event = sqs_event("arn:aws:sqs:us-east-1:123:queue", &["ok", "bad", "ok", "bad"]);
+        let response = BatchProcessor::new(fail_on_bad).process(event).await.unwrap();
+        assert_eq!(failures(response), ["m1", "m3"]);
+
+        let event = sqs_event("arn:aws:sqs:us-east-1:123:queue", &["bad", "ok", "bad", "ok"]);
+        let processor = BatchProcessor::new(fail_on_bad).with_concurrency(3);
+        let response = processor.process(event).await.unwrap();
+        assert_eq!(failures(response), ["m0", "m2"]);
+    }
+
+    #[tokio::test]
+    async fn ordered_sources_stop_at_the_first_failure() {
+        let processed = Mutex::new(Vec::new());
+        let processor = BatchProcessor::new(|message: SqsMessage| {
+            processed.lock().unwrap().push(message.message_id.clone().unwrap());
+            fail_on_bad(message)
+        })
+        .with_concurrency(10);
+
+        let event = sqs_event("arn:aws:sqs:us-east-1:123:queue.fifo", &["ok", "bad", "ok"]);
+        let response = processor.process(event).await.unwrap();
+        assert_eq!(failures(response), ["m1", "m2"]);
+        assert_eq!(*processed.lock().unwrap(), ["m0", "m1"]);
+    }
+
+    #[tokio::test]
+    async fn kinesis_response() {
+        let record = |sequence_number: &str, data: &str| {
+            json!({
+                "kinesis": {
+                    "approximateArrivalTimestamp": 1_700_000_000.0,
+                    "data": data,
+                    "partitionKey": "key",
+                    "sequenceNumber": sequence_number,
+                }
+            })
+        };
+        // "b2s=" is "ok", "YmFk" is "bad"
+        let event: KinesisEvent = serde_json::from_value(json!({
+            "Records": [record("1", "b2s="), record("2", "YmFk"), record("3", "b2s=")]
+        }))
+        .unwrap();
+        let processor = BatchProcessor::new(|record: KinesisEventRecord| async move {
+            match &record.kinesis.data.0[..] {
+                b"bad" => Err(Error::from("bad record")),
+                _ => Ok(()),
+            }
+        });
+        let response = processor.process(event).await.unwrap();
+        let ids: Vec<_> = response
+            .batch_item_failures
+            .into_iter()
+            .map(|failure| failure.item_identifier.unwrap())
+            .collect();
+        assert_eq!(ids, ["2", "3"]);
+    }
+
+    #[tokio::test]
+    async fn failure_without_identifier() {
+        let event: SqsEvent = serde_json::from_value(json!({ "Records": [{ "body": "bad" }] })).unwrap();
+        let diagnostic = BatchProcessor::new(fail_on_bad).process(event).await.unwrap_err();
+        assert_eq!(diagnostic.error_type, MISSING_IDENTIFIER_ERROR_TYPE);
+    }
+}
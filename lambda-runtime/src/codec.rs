@@ -0,0 +1,221 @@
+//! Codecs to decode the event payloads sent to a function, and to encode its responses.
+//!
+//! The runtime uses [JsonCodec] by default. Use [RuntimeBuilder::with_codec](crate::RuntimeBuilder::with_codec)
+//! to select a different codec. Handlers built with [extract::handler](crate::extract::handler) only
+//! support [JsonCodec], see [Payload codecs](crate::extract#payload-codecs).
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, fmt::Debug};
+
+use crate::{deserializer, Borrowed, Diagnostic, Error};
+
+pub use crate::deserializer::DeserializeError;
+
+/// Codec that converts the raw payloads exchanged with the Lambda Runtime API
+/// from and into the types used by a handler.
+///
+/// `Event` is the payload type that the handler receives, and `Response` is the type of
+/// the buffered responses that the handler returns. Streaming responses are sent as they
+/// are produced, without going through the codec.
+pub trait PayloadCodec<Event, Response> {
+    /// Error returned when the event payload cannot be decoded.
+    type DecodeError: Debug;
+
+    /// Decode the payload of an invocation into the handler's event type.
+    fn decode(&self, body: Bytes) -> Result<Event, Self::DecodeError>;
+
+    /// Encode a buffered response of the handler into the payload sent to the Lambda Runtime API.
+    fn encode(&self, response: Response) -> Result<Bytes, Error>;
+
+    /// Convert a decoding error into the [Diagnostic] reported for the invocation.
+    /// The handler is not called when the event payload cannot be decoded.
+    fn decode_error(&self, error: Self::DecodeError) -> Diagnostic;
+}
+
+/// Codec that uses `serde_json`. This is the default codec of the runtime.
+///
+/// Decoding errors include the path of the field that could not be deserialized.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonCodec;
+
+impl<Event, Response> PayloadCodec<Event, Response> for JsonCodec
+where
+    Event: for<'de> Deserialize<'de>,
+    Response: Serialize,
+{
+    type DecodeError = deserializer::DeserializeError;
+
+    fn decode(&self, body: Bytes) -> Result<Event, Self::DecodeError> {
+        deserializer::deserialize(&body)
+    }
+
+    fn encode(&self, response: Response) -> Result<Bytes, Error> {
+        Ok(serde_json::to_vec(&response)?.into())
+    }
+
+    fn decode_error(&self, error: Self::DecodeError) -> Diagnostic {
+        error.into()
+    }
+}
+
+/// Events received as [Borrowed] payloads are deserialized by the handler, so they're
+/// passed through without being parsed.
+impl<Response> PayloadCodec<Borrowed, Response> for JsonCodec
+where
+    Response: Serialize,
+{
+    type DecodeError = Infallible;
+
+    fn decode(&self, body: Bytes) -> Result<Borrowed, Self::DecodeError> {
+        Ok(Borrowed::new(body))
+    }
+
+    fn encode(&self, response: Response) -> Result<Bytes, Error> {
+        Ok(serde_json::to_vec(&response)?.into())
+    }
+
+    fn decode_error(&self, error: Self::DecodeError) -> Diagnostic {
+        error.into()
+    }
+}
+
+/// Codec that uses `simd-json`, which parses large payloads faster than `serde_json`
+/// on CPUs with SIMD support.
+#[cfg(feature = "simd-json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "simd-json")))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SimdJsonCodec;
+
+#[cfg(feature = "simd-json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "simd-json")))]
+impl<Event, Response> PayloadCodec<Event, Response> for SimdJsonCodec
+where
+    Event: for<'de> Deserialize<'de>,
+    Response: Serialize,
+{
+    type DecodeError = simd_json::Error;
+
+    fn decode(&self, body: Bytes) -> Result<Event, Self::DecodeError> {
+        // simd-json parses the payload in place, so it needs its own mutable copy.
+        let mut body = body.to_vec();
+        simd_json::serde::from_slice(&mut body)
+    }
+
+    fn encode(&self, response: Response) -> Result<Bytes, Error> {
+        Ok(simd_json::serde::to_vec(&response)?.into())
+    }
+
+    fn decode_error(&self, error: Self::DecodeError) -> Diagnostic {
+        Diagnostic {
+            error_type: crate::diagnostic::type_name_of_val(&error),
+            error_message: format!("failed to deserialize the incoming data into the function's payload type: {error}"),
+            ..Default::default()
+        }
+    }
+}
+
+/// Codec that gives the raw payload of an invocation to the handler, without any
+/// deserialization. Buffered responses are sent as they are returned by the handler.
+///
+/// # Example
+/// ```no_run
+/// use bytes::Bytes;
+/// use lambda_runtime::{codec::RawCodec, Error, LambdaEvent, RuntimeBuilder};
+/// use tower::service_fn;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let runtime = RuntimeBuilder::new()
+///         .with_codec(RawCodec)
+///         .build(service_fn(func))?;
+///     runtime.run().await
+/// }
+///
+/// async fn func(event: LambdaEvent<Bytes>) -> Result<Vec<u8>, Error> {
+///     Ok(event.payload.to_ascii_uppercase())
+/// }
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RawCodec;
+
+impl<Response> PayloadCodec<Bytes, Response> for RawCodec
+where
+    Response: Into<Bytes>,
+{
+    type DecodeError = Infallible;
+
+    fn decode(&self, body: Bytes) -> Result<Bytes, Self::DecodeError> {
+        Ok(body)
+    }
+
+    fn encode(&self, response: Response) -> Result<Bytes, Error> {
+        Ok(response.into())
+    }
+
+    fn decode_error(&self, error: Self::DecodeError) -> Diagnostic {
+        error.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Order {
+        id: u32,
+        items: Vec<String>,
+    }
+
+    #[test]
+    fn json_codec() {
+        let codec = JsonCodec;
+        let order: Order = PayloadCodec::<_, ()>::decode(&codec, Bytes::from(r#"{"id":1,"items":["book"]}"#)).unwrap();
+        assert_eq!(
+            order,
+            Order {
+                id: 1,
+                items: vec!["book".into()]
+            }
+        );
+
+        let body = PayloadCodec::<Value, _>::encode(&codec, order).unwrap();
+        assert_eq!(body, Bytes::from(r#"{"id":1,"items":["book"]}"#));
+
+        let err = PayloadCodec::<Order, ()>::decode(&codec, Bytes::from(r#"{"id":"1"}"#)).unwrap_err();
+        let diagnostic = PayloadCodec::<Order, ()>::decode_error(&codec, err);
+        assert!(
+            diagnostic.error_message.contains("[id]"),
+            "{}",
+            diagnostic.error_message
+        );
+    }
+
+    #[cfg(feature = "simd-json")]
+    #[test]
+    fn simd_json_codec() {
+        let codec = SimdJsonCodec;
+        let order: Order = PayloadCodec::<_, ()>::decode(&codec, Bytes::from(r#"{"id":1,"items":["book"]}"#)).unwrap();
+        assert_eq!(order.items, vec!["book"]);
+
+        let body = PayloadCodec::<Value, _>::encode(&codec, serde_json::json!({"id": 1})).unwrap();
+        assert_eq!(body, Bytes::from(r#"{"id":1}"#));
+
+        let err = PayloadCodec::<Order, ()>::decode(&codec, Bytes::from("{")).unwrap_err();
+        let diagnostic = PayloadCodec::<Order, ()>::decode_error(&codec, err);
+        assert!(diagnostic
+            .error_message
+            .starts_with("failed to deserialize the incoming data into the function's payload type"));
+    }
+
+    #[test]
+    fn raw_codec() {
+        let body = Bytes::from_static(b"\x00not json");
+        let event = PayloadCodec::<_, Vec<u8>>::decode(&RawCodec, body.clone()).unwrap();
+        assert_eq!(event, body);
+
+        let response = PayloadCodec::<Bytes, _>::encode(&RawCodec, b"raw response".to_vec()).unwrap();
+        assert_eq!(response, Bytes::from_static(b"raw response"));
+    }
+}
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
-use std::any::type_name;
+use std::{
+    any::type_name,
+    backtrace::{Backtrace, BacktraceStatus},
+};
 
 use crate::{deserializer::DeserializeError, Error};
 
@@ -18,6 +21,14 @@ use crate::{deserializer::DeserializeError, Error};
 /// To get more descriptive [`error_type`][`Diagnostic::error_type`] fields, you can implement `From` for your error type.
 /// That gives you full control on what the `error_type` is.
 ///
+/// The general implementations also fill [`causes`][`Diagnostic::causes`] with the
+/// chain of errors returned by [`source`][std::error::Error::source]. The `anyhow`
+/// integration fills [`stack_trace`][`Diagnostic::stack_trace`] with the error's
+/// backtrace when backtraces are enabled with `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`.
+/// The other implementations leave it empty, since the backtrace of a [`std::error::Error`]
+/// can't be read on stable Rust. To report it, capture a [`Backtrace`] where the error is
+/// created, and call [`Diagnostic::with_backtrace`] in your own `From` implementation.
+///
 /// Example:
 /// ```
 /// use lambda_runtime::{Diagnostic, Error, LambdaEvent};
@@ -30,6 +41,7 @@ use crate::{deserializer::DeserializeError, Error};
 ///         Diagnostic {
 ///             error_type: "MyError".into(),
 ///             error_message: error.0.to_string(),
+///             ..Default::default()
 ///         }
 ///     }
 /// }
@@ -38,7 +50,7 @@ use crate::{deserializer::DeserializeError, Error};
 ///    Err(ErrorResponse("this is an error response"))
 /// }
 /// ```
-#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Diagnostic {
     /// `error_type` is the type of exception or error returned by the function.
@@ -53,6 +65,68 @@ pub struct Diagnostic {
     /// In standard implementations, it's the output from the [`Display`][std::fmt::Display]
     /// implementation of the original error.
     pub error_message: String,
+    /// `stack_trace` is the list of stack frames where the error was captured,
+    /// with the innermost frame first. It's omitted from the payload when empty.
+    ///
+    /// Use [`Diagnostic::with_backtrace`] to fill it from a [`Backtrace`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stack_trace: Vec<String>,
+    /// `causes` is the chain of errors that caused this error, from the immediate
+    /// cause to the root cause. It's omitted from the payload when empty.
+    ///
+    /// Use [`Diagnostic::with_causes`] to fill it from an error's [`source`][std::error::Error::source].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub causes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic with an error type and message, and without stack trace nor causes.
+    pub fn new(error_type: impl Into<String>, error_message: impl Into<String>) -> Self {
+        Diagnostic {
+            error_type: error_type.into(),
+            error_message: error_message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the [`stack_trace`][`Diagnostic::stack_trace`] from the frames of a backtrace.
+    ///
+    /// Backtraces that were not captured, see [`Backtrace::status`], leave the stack trace empty.
+    ///
+    /// ```
+    /// use lambda_runtime::Diagnostic;
+    /// use std::backtrace::Backtrace;
+    ///
+    /// #[derive(Debug)]
+    /// struct OrderError {
+    ///     message: String,
+    ///     backtrace: Backtrace,
+    /// }
+    ///
+    /// impl OrderError {
+    ///     fn new(message: impl Into<String>) -> Self {
+    ///         let backtrace = Backtrace::capture();
+    ///         OrderError { message: message.into(), backtrace }
+    ///     }
+    /// }
+    ///
+    /// impl From<OrderError> for Diagnostic {
+    ///     fn from(error: OrderError) -> Diagnostic {
+    ///         Diagnostic::new("OrderError", error.message).with_backtrace(&error.backtrace)
+    ///     }
+    /// }
+    /// ```
+    pub fn with_backtrace(mut self, backtrace: &Backtrace) -> Self {
+        self.stack_trace = stack_frames(backtrace);
+        self
+    }
+
+    /// Set the [`causes`][`Diagnostic::causes`] from the chain of errors
+    /// returned by [`source`][std::error::Error::source].
+    pub fn with_causes(mut self, error: &(dyn std::error::Error + 'static)) -> Self {
+        self.causes = error_causes(error);
+        self
+    }
 }
 
 impl From<DeserializeError> for Diagnostic {
@@ -60,6 +134,8 @@ impl From<DeserializeError> for Diagnostic {
         Diagnostic {
             error_type: type_name_of_val(&value),
             error_message: value.to_string(),
+            causes: error_causes(&value),
+            ..Default::default()
         }
     }
 }
@@ -69,6 +145,8 @@ impl From<Error> for Diagnostic {
         Diagnostic {
             error_type: type_name_of_val(&value),
             error_message: value.to_string(),
+            causes: error_causes(value.as_ref()),
+            ..Default::default()
         }
     }
 }
@@ -78,6 +156,8 @@ impl From<Box<dyn std::error::Error>> for Diagnostic {
         Diagnostic {
             error_type: type_name_of_val(&value),
             error_message: value.to_string(),
+            causes: error_causes(value.as_ref()),
+            ..Default::default()
         }
     }
 }
@@ -87,6 +167,7 @@ impl From<std::convert::Infallible> for Diagnostic {
         Diagnostic {
             error_type: type_name_of_val(&value),
             error_message: value.to_string(),
+            ..Default::default()
         }
     }
 }
@@ -96,6 +177,7 @@ impl From<String> for Diagnostic {
         Diagnostic {
             error_type: type_name_of_val(&value),
             error_message: value.to_string(),
+            ..Default::default()
         }
     }
 }
@@ -105,6 +187,7 @@ impl From<&'static str> for Diagnostic {
         Diagnostic {
             error_type: type_name_of_val(&value),
             error_message: value.to_string(),
+            ..Default::default()
         }
     }
 }
@@ -114,6 +197,8 @@ impl From<std::io::Error> for Diagnostic {
         Diagnostic {
             error_type: type_name_of_val(&value),
             error_message: value.to_string(),
+            causes: error_causes(&value),
+            ..Default::default()
         }
     }
 }
@@ -125,6 +210,8 @@ impl From<anyhow::Error> for Diagnostic {
         Diagnostic {
             error_type: type_name_of_val(&value),
             error_message: value.to_string(),
+            stack_trace: stack_frames(value.backtrace()),
+            causes: value.chain().skip(1).map(|err| err.to_string()).collect(),
         }
     }
 }
@@ -136,6 +223,8 @@ impl From<eyre::Report> for Diagnostic {
         Diagnostic {
             error_type: type_name_of_val(&value),
             error_message: value.to_string(),
+            causes: value.chain().skip(1).map(|err| err.to_string()).collect(),
+            ..Default::default()
         }
     }
 }
@@ -147,6 +236,8 @@ impl From<miette::Report> for Diagnostic {
         Diagnostic {
             error_type: type_name_of_val(&value),
             error_message: value.to_string(),
+            causes: value.chain().skip(1).map(|err| err.to_string()).collect(),
+            ..Default::default()
         }
     }
 }
@@ -155,6 +246,45 @@ pub(crate) fn type_name_of_val<T>(_: T) -> String {
     type_name::<T>().into()
 }
 
+fn error_causes(error: &(dyn std::error::Error + 'static)) -> Vec<String> {
+    let mut causes = Vec::new();
+    let mut source = error.source();
+    while let Some(err) = source {
+        causes.push(err.to_string());
+        source = err.source();
+    }
+    causes
+}
+
+fn stack_frames(backtrace: &Backtrace) -> Vec<String> {
+    if backtrace.status() != BacktraceStatus::Captured {
+        return Vec::new();
+    }
+    parse_frames(&backtrace.to_string())
+}
+
+/// Collapse the `Display` output of a backtrace into one line per frame,
+/// joining each symbol with its source location.
+fn parse_frames(backtrace: &str) -> Vec<String> {
+    let mut frames: Vec<String> = Vec::new();
+    for line in backtrace.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match (line.strip_prefix("at "), frames.last_mut()) {
+            (Some(location), Some(frame)) => {
+                frame.push_str(" at ");
+                frame.push_str(location);
+            }
+            _ => {
+                let symbol = match line.split_once(": ") {
+                    Some((index, symbol)) if index.chars().all(|c| c.is_ascii_digit()) => symbol,
+                    _ => line,
+                };
+                frames.push(symbol.to_string());
+            }
+        }
+    }
+    frames
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -170,11 +300,89 @@ mod test {
         let actual = Diagnostic {
             error_type: "InvalidEventDataError".into(),
             error_message: "Error parsing event data.".into(),
+            ..Default::default()
         };
         let actual: Value = serde_json::to_value(actual).expect("failed to serialize diagnostic");
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn round_trip_lambda_error_with_trace() {
+        use serde_json::{json, Value};
+        let expected = json!({
+            "errorType": "InvalidEventDataError",
+            "errorMessage": "Error parsing event data.",
+            "stackTrace": ["handler at ./src/main.rs:10:5"],
+            "causes": ["missing field `name`"],
+        });
+
+        let diagnostic = Diagnostic {
+            error_type: "InvalidEventDataError".into(),
+            error_message: "Error parsing event data.".into(),
+            stack_trace: vec!["handler at ./src/main.rs:10:5".into()],
+            causes: vec!["missing field `name`".into()],
+        };
+        let actual: Value = serde_json::to_value(&diagnostic).expect("failed to serialize diagnostic");
+        assert_eq!(expected, actual);
+
+        let parsed: Diagnostic = serde_json::from_value(actual).expect("failed to deserialize diagnostic");
+        assert_eq!(diagnostic, parsed);
+    }
+
+    #[derive(Debug)]
+    struct Nested(&'static str, Option<Box<Nested>>);
+
+    impl std::fmt::Display for Nested {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl std::error::Error for Nested {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            self.1.as_deref().map(|err| err as _)
+        }
+    }
+
+    #[test]
+    fn error_source_chain() {
+        let root = Nested("connection refused", None);
+        let middle = Nested("unable to reach the database", Some(Box::new(root)));
+        let error: Error = Box::new(Nested("failed to load order", Some(Box::new(middle))));
+
+        let diagnostic: Diagnostic = error.into();
+        assert_eq!(diagnostic.error_message, "failed to load order");
+        assert_eq!(
+            diagnostic.causes,
+            vec!["unable to reach the database", "connection refused"]
+        );
+        assert!(diagnostic.stack_trace.is_empty());
+    }
+
+    #[test]
+    fn backtrace_frames() {
+        let backtrace = "   0: my_function::handler
+             at ./src/main.rs:10:5
+   1: lambda_runtime::run::{{closure}}
+   2: std::rt::lang_start
+             at /rustc/library/std/src/rt.rs:159:18
+";
+        assert_eq!(
+            parse_frames(backtrace),
+            vec![
+                "my_function::handler at ./src/main.rs:10:5",
+                "lambda_runtime::run::{{closure}}",
+                "std::rt::lang_start at /rustc/library/std/src/rt.rs:159:18",
+            ]
+        );
+
+        let diagnostic = Diagnostic::from("error").with_backtrace(&Backtrace::disabled());
+        assert!(diagnostic.stack_trace.is_empty());
+
+        let diagnostic = Diagnostic::from("error").with_backtrace(&Backtrace::force_capture());
+        assert!(!diagnostic.stack_trace.is_empty());
+    }
+
     #[cfg(feature = "anyhow")]
     #[test]
     fn test_anyhow_integration() {
@@ -183,6 +391,12 @@ mod test {
         let diagnostic: Diagnostic = error.into();
         assert_eq!(diagnostic.error_type, "&anyhow::Error");
         assert_eq!(diagnostic.error_message, "anyhow error");
+        assert!(diagnostic.causes.is_empty());
+
+        let error = anyhow::anyhow!("connection refused").context("unable to reach the database");
+        let diagnostic: Diagnostic = error.into();
+        assert_eq!(diagnostic.error_message, "unable to reach the database");
+        assert_eq!(diagnostic.causes, vec!["connection refused"]);
     }
 
     #[cfg(feature = "eyre")]
@@ -193,6 +407,10 @@ mod test {
         let diagnostic: Diagnostic = error.into();
         assert_eq!(diagnostic.error_type, "&eyre::Report");
         assert_eq!(diagnostic.error_message, "eyre error");
+
+        let error = eyre::eyre!("connection refused").wrap_err("unable to reach the database");
+        let diagnostic: Diagnostic = error.into();
+        assert_eq!(diagnostic.causes, vec!["connection refused"]);
     }
 
     #[cfg(feature = "miette")]
@@ -203,5 +421,9 @@ mod test {
         let diagnostic: Diagnostic = error.into();
         assert_eq!(diagnostic.error_type, "&miette::eyreish::Report");
         assert_eq!(diagnostic.error_message, "miette error");
+
+        let error = miette::miette!("connection refused").wrap_err("unable to reach the database");
+        let diagnostic: Diagnostic = error.into();
+        assert_eq!(diagnostic.causes, vec!["connection refused"]);
     }
 }
@@ -0,0 +1,365 @@
+//! Typed extractors to write handlers that only take the parts of an invocation they need.
+//!
+//! Instead of a [LambdaEvent], a handler built with [handler] can take any number of arguments
+//! that implement [FromInvocation], like the deserialized [Payload], the [Context] of the
+//! invocation, or the [State] shared by all the invocations:
+//!
+//! ```no_run
+//! use lambda_runtime::{extract::{handler, Deadline, Payload, State}, Error};
+//! use serde::Deserialize;
+//! use std::sync::Arc;
+//!
+//! #[derive(Deserialize)]
+//! struct Order {
+//!     id: String,
+//! }
+//!
+//! struct AppState {
+//!     table_name: String,
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let state = Arc::new(AppState {
+//!         table_name: std::env::var("TABLE_NAME")?,
+//!     });
+//!     lambda_runtime::run(handler(func).with_state(state)).await
+//! }
+//!
+//! async fn func(
+//!     Payload(order): Payload<Order>,
+//!     State(state): State<Arc<AppState>>,
+//!     deadline: Deadline,
+//! ) -> Result<String, Error> {
+//!     Ok(format!("{} saved in {}, {:?} left", order.id, state.table_name, deadline.remaining()))
+//! }
+//! ```
+//!
+//! # Payload codecs
+//!
+//! Extractors decode the payload of the invocation themselves, as JSON with `serde_json`, so
+//! handlers built with [handler] only run with the default [JsonCodec](crate::codec::JsonCodec).
+//! Building a runtime for them with another [codec](crate::codec), like `SimdJsonCodec`
+//! or [RawCodec](crate::codec::RawCodec), fails to compile:
+//!
+//! ```compile_fail
+//! use lambda_runtime::{codec::RawCodec, extract::{handler, Payload}, Error, RuntimeBuilder};
+//! use serde_json::Value;
+//!
+//! async fn func(Payload(event): Payload<Value>) -> Result<Value, Error> {
+//!     Ok(event)
+//! }
+//!
+//! let runtime = RuntimeBuilder::new().with_codec(RawCodec).build(handler(func));
+//! ```
+use crate::{deserializer, Borrowed, Context, Diagnostic, LambdaEvent};
+use bytes::Bytes;
+use pin_project::pin_project;
+use serde::de::DeserializeOwned;
+use std::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task,
+    time::{Duration, SystemTime},
+};
+use tower::Service;
+
+/// Types that can be created from the raw payload and the [Context] of an invocation,
+/// to be used as arguments of a [Handler]. The headers of the invocation are already parsed
+/// into its [Context].
+///
+/// `S` is the type of the state given to [HandlerService::with_state].
+/// When an extractor fails, the handler is not called, and the invocation fails with its [Diagnostic].
+pub trait FromInvocation<S>: Sized {
+    /// Extract the value from the payload and the context of the invocation.
+    fn from_invocation(payload: &Bytes, context: &Context, state: &S) -> Result<Self, Diagnostic>;
+}
+
+/// Extractor that deserializes the JSON payload of the invocation with `serde_json`.
+/// See [Payload codecs](self#payload-codecs).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Payload<T>(pub T);
+
+impl<T, S> FromInvocation<S> for Payload<T>
+where
+    T: DeserializeOwned,
+{
+    fn from_invocation(payload: &Bytes, _context: &Context, _state: &S) -> Result<Self, Diagnostic> {
+        Ok(Payload(deserializer::deserialize(payload)?))
+    }
+}
+
+/// The whole [LambdaEvent], with its deserialized payload and context. It lets handlers that take
+/// a `LambdaEvent` be used with [handler], and combined with other extractors like [State].
+impl<T, S> FromInvocation<S> for LambdaEvent<T>
+where
+    T: DeserializeOwned,
+{
+    fn from_invocation(payload: &Bytes, context: &Context, _state: &S) -> Result<Self, Diagnostic> {
+        let payload = deserializer::deserialize(payload)?;
+        Ok(LambdaEvent::new(payload, context.clone()))
+    }
+}
+
+/// Extractor for the [Context] of the invocation.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Ctx<T = Context>(pub T);
+
+impl<S> FromInvocation<S> for Ctx<Context> {
+    fn from_invocation(_payload: &Bytes, context: &Context, _state: &S) -> Result<Self, Diagnostic> {
+        Ok(Ctx(context.clone()))
+    }
+}
+
+/// Extractor for the state given to [HandlerService::with_state]. The state is cloned for
+/// every invocation, wrap it in an [Arc](std::sync::Arc) if it's expensive to clone.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct State<S>(pub S);
+
+impl<S> FromInvocation<S> for State<S>
+where
+    S: Clone,
+{
+    fn from_invocation(_payload: &Bytes, _context: &Context, state: &S) -> Result<Self, Diagnostic> {
+        Ok(State(state.clone()))
+    }
+}
+
+/// Extractor for the [deadline](Context::deadline) of the invocation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Deadline(pub SystemTime);
+
+impl Deadline {
+    /// Time left before the deadline, or zero when it has already passed.
+    pub fn remaining(&self) -> Duration {
+        self.0.duration_since(SystemTime::now()).unwrap_or_default()
+    }
+}
+
+impl<S> FromInvocation<S> for Deadline {
+    fn from_invocation(_payload: &Bytes, context: &Context, _state: &S) -> Result<Self, Diagnostic> {
+        Ok(Deadline(context.deadline()))
+    }
+}
+
+/// Async functions that take [FromInvocation] extractors as arguments.
+///
+/// It's implemented for functions with up to eight arguments, which return a future of a `Result`.
+/// `Args` is the tuple of the argument types, and `S` the type of the state.
+pub trait Handler<Args, S> {
+    /// Response of the handler.
+    type Response;
+    /// Error returned by the handler.
+    type Error: Into<Diagnostic>;
+    /// Future returned by the handler.
+    type Future: Future<Output = Result<Self::Response, Self::Error>>;
+
+    /// Extract the arguments from the payload and the context of the invocation,
+    /// and call the handler with them.
+    fn call(&self, payload: &Bytes, context: &Context, state: &S) -> Result<Self::Future, Diagnostic>;
+}
+
+macro_rules! impl_handler {
+    ($($arg:ident),*) => {
+        #[allow(non_snake_case, unused_variables)]
+        impl<F, Fut, R, E, S, $($arg,)*> Handler<($($arg,)*), S> for F
+        where
+            F: Fn($($arg),*) -> Fut,
+            Fut: Future<Output = Result<R, E>>,
+            E: Into<Diagnostic>,
+            $($arg: FromInvocation<S>,)*
+        {
+            type Response = R;
+            type Error = E;
+            type Future = Fut;
+
+            fn call(&self, payload: &Bytes, context: &Context, state: &S) -> Result<Self::Future, Diagnostic> {
+                $(let $arg = $arg::from_invocation(payload, context, state)?;)*
+                Ok(self($($arg),*))
+            }
+        }
+    };
+}
+
+impl_handler!();
+impl_handler!(T1);
+impl_handler!(T1, T2);
+impl_handler!(T1, T2, T3);
+impl_handler!(T1, T2, T3, T4);
+impl_handler!(T1, T2, T3, T4, T5);
+impl_handler!(T1, T2, T3, T4, T5, T6);
+impl_handler!(T1, T2, T3, T4, T5, T6, T7);
+impl_handler!(T1, T2, T3, T4, T5, T6, T7, T8);
+
+/// Create a service for [run](crate::run) or [Runtime::new](crate::Runtime::new) from a function
+/// that takes extractors as arguments.
+///
+/// The service receives the payload without deserializing it, as a [Borrowed] payload,
+/// and each extractor takes what it needs from the payload and the [Context] of the invocation.
+pub fn handler<H, Args>(handler: H) -> HandlerService<H, Args, ()> {
+    HandlerService {
+        handler,
+        state: (),
+        _args: PhantomData,
+    }
+}
+
+/// Tower service returned by [handler].
+pub struct HandlerService<H, Args, S> {
+    handler: H,
+    state: S,
+    _args: PhantomData<fn() -> Args>,
+}
+
+impl<H> HandlerService<H, (), ()> {
+    /// Give a state to the handler, which it receives with the [State] extractor.
+    pub fn with_state<S, Args>(self, state: S) -> HandlerService<H, Args, S>
+    where
+        H: Handler<Args, S>,
+    {
+        HandlerService {
+            handler: self.handler,
+            state,
+            _args: PhantomData,
+        }
+    }
+}
+
+impl<H: Clone, Args, S: Clone> Clone for HandlerService<H, Args, S> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+            state: self.state.clone(),
+            _args: PhantomData,
+        }
+    }
+}
+
+impl<H, Args, S> Service<LambdaEvent<Borrowed>> for HandlerService<H, Args, S>
+where
+    H: Handler<Args, S>,
+{
+    type Response = H::Response;
+    type Error = Diagnostic;
+    type Future = HandlerFuture<H::Future>;
+
+    fn poll_ready(&mut self, _cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: LambdaEvent<Borrowed>) -> Self::Future {
+        let payload = req.payload.into_bytes();
+        match self.handler.call(&payload, &req.context, &self.state) {
+            Ok(future) => HandlerFuture::Running { future },
+            Err(diagnostic) => HandlerFuture::Rejected {
+                diagnostic: Some(diagnostic),
+            },
+        }
+    }
+}
+
+/// Future returned by [HandlerService].
+#[pin_project(project = HandlerFutureProj)]
+pub enum HandlerFuture<F> {
+    /// The arguments were extracted, and the handler is running.
+    Running {
+        /// Future of the handler.
+        #[pin]
+        future: F,
+    },
+    /// An extractor failed, and the handler was not called.
+    Rejected {
+        /// Error of the extractor.
+        diagnostic: Option<Diagnostic>,
+    },
+}
+
+impl<F, R, E> Future for HandlerFuture<F>
+where
+    F: Future<Output = Result<R, E>>,
+    E: Into<Diagnostic>,
+{
+    type Output = Result<R, Diagnostic>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
+        match self.project() {
+            HandlerFutureProj::Running { future } => future.poll(cx).map_err(Into::into),
+            HandlerFutureProj::Rejected { diagnostic } => {
+                task::Poll::Ready(Err(diagnostic.take().expect("future polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use serde::Deserialize;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[derive(Deserialize)]
+    struct Order {
+        id: String,
+    }
+
+    fn event(payload: &'static str) -> LambdaEvent<Borrowed> {
+        let context = Context {
+            request_id: "request-1".into(),
+            deadline: 1_000,
+            ..Default::default()
+        };
+        LambdaEvent::new(Borrowed::new(payload), context)
+    }
+
+    #[tokio::test]
+    async fn extract_arguments() {
+        async fn func(
+            Payload(order): Payload<Order>,
+            Ctx(context): Ctx,
+            State(prefix): State<Arc<str>>,
+            deadline: Deadline,
+        ) -> Result<String, Error> {
+            assert_eq!(deadline.0, SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+            assert_eq!(deadline.remaining(), Duration::ZERO);
+            Ok(format!("{prefix}-{}-{}", order.id, context.request_id))
+        }
+
+        let service = handler(func).with_state(Arc::<str>::from("order"));
+        let response = service.oneshot(event(r#"{"id": "1234"}"#)).await.unwrap();
+        assert_eq!(response, "order-1234-request-1");
+    }
+
+    #[tokio::test]
+    async fn custom_extractor() {
+        struct RequestId(String);
+
+        impl<S> FromInvocation<S> for RequestId {
+            fn from_invocation(_payload: &Bytes, context: &Context, _state: &S) -> Result<Self, Diagnostic> {
+                Ok(RequestId(context.request_id.clone()))
+            }
+        }
+
+        let service = handler(|RequestId(id): RequestId| async move { Ok::<_, Error>(id) });
+        assert_eq!(service.oneshot(event("{}")).await.unwrap(), "request-1");
+    }
+
+    #[tokio::test]
+    async fn extractor_failure() {
+        let service = handler(|Payload(order): Payload<Order>| async move { Ok::<_, Error>(order.id) });
+        let diagnostic = service.oneshot(event(r#"{"id": 1234}"#)).await.unwrap_err();
+        assert_eq!(diagnostic.error_type, "&lambda_runtime::deserializer::DeserializeError");
+
+        let service = handler(|event: LambdaEvent<Order>, State(()): State<()>| async move {
+            Err::<(), _>(Error::from(format!("order {} failed", event.payload.id)))
+        });
+        let diagnostic = service.oneshot(event(r#"{"id": "1234"}"#)).await.unwrap_err();
+        assert_eq!(diagnostic.error_message, "order 1234 failed");
+
+        let service = handler(|| async { Err::<(), _>(Error::from("handler failed")) });
+        let diagnostic = service.oneshot(event("{}")).await.unwrap_err();
+        assert_eq!(diagnostic.error_message, "handler failed");
+    }
+}
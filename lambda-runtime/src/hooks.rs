@@ -0,0 +1,309 @@
+use crate::{
+    requests::{InitErrorRequest, IntoRequest, RestoreErrorRequest, RestoreNextRequest},
+    Context, Diagnostic,
+};
+use futures::future::BoxFuture;
+use lambda_runtime_api_client::{BoxError, Client as ApiClient};
+use std::{fmt::Debug, future::Future, sync::Arc};
+
+type OnceHook = Box<dyn FnOnce() -> BoxFuture<'static, Result<(), Diagnostic>> + Send>;
+type BeforeInvokeHook = Arc<dyn Fn(&Context) + Send + Sync>;
+type AfterInvokeHook = Arc<dyn Fn(&Context, Result<(), &Diagnostic>) + Send + Sync>;
+#[cfg(all(unix, feature = "graceful-shutdown"))]
+type ShutdownHook = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+/// Functions that the runtime calls at the different stages of the lifecycle of a Lambda function.
+///
+/// Hooks are a lightweight alternative to writing a [tower::Layer] when you only need to run
+/// some code around invocations, like flushing a metrics buffer after each invocation.
+/// Several hooks can be registered for the same stage, they are called in registration order.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{Error, Hooks, LambdaEvent, RuntimeBuilder};
+/// use serde_json::Value;
+/// use tower::service_fn;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let hooks = Hooks::new()
+///         .on_init(|| async { Ok::<_, Error>(()) })
+///         .before_invoke(|context| println!("starting invocation {}", context.request_id))
+///         .after_invoke(|context, result| {
+///             println!("invocation {} succeeded: {}", context.request_id, result.is_ok())
+///         });
+///
+///     let runtime = RuntimeBuilder::new().with_hooks(hooks).build(service_fn(func))?;
+///     runtime.run().await
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+#[derive(Default)]
+pub struct Hooks {
+    pub(crate) lifecycle: LifecycleHooks,
+    pub(crate) invocation: InvocationHooks,
+}
+
+impl Hooks {
+    /// Create a new set of hooks without any function registered.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run a function once, when the runtime starts and before it polls for the first event.
+    ///
+    /// If the function fails, the error is reported to the Lambda Runtime API as an
+    /// [init error](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html#runtimes-api-initerror),
+    /// and the runtime stops without processing any invocation.
+    pub fn on_init<F, Fut, E>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<Diagnostic> + Debug,
+    {
+        self.lifecycle.init.push(once_hook(hook, "init"));
+        self
+    }
+
+    /// Run a function before Lambda takes the [SnapStart](https://docs.aws.amazon.com/lambda/latest/dg/snapstart.html)
+    /// snapshot of the execution environment, after the `on_init` hooks.
+    ///
+    /// Use it to close the network connections and to clear the state that must not be
+    /// shared by all the environments restored from the snapshot. Failures are reported
+    /// to the Lambda Runtime API as init errors. The hook only runs in SnapStart mode,
+    /// see [RuntimeBuilder::with_snap_start](crate::RuntimeBuilder::with_snap_start).
+    pub fn before_checkpoint<F, Fut, E>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<Diagnostic> + Debug,
+    {
+        self.lifecycle
+            .before_checkpoint
+            .push(once_hook(hook, "before checkpoint"));
+        self
+    }
+
+    /// Run a function after the execution environment is restored from a
+    /// [SnapStart](https://docs.aws.amazon.com/lambda/latest/dg/snapstart.html) snapshot,
+    /// before it polls for the first event.
+    ///
+    /// Use it to open new network connections, and to re-seed random number generators and
+    /// unique ids. Failures are reported to the Lambda Runtime API as restore errors.
+    /// The hook only runs in SnapStart mode, see [RuntimeBuilder::with_snap_start](crate::RuntimeBuilder::with_snap_start).
+    pub fn after_restore<F, Fut, E>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<Diagnostic> + Debug,
+    {
+        self.lifecycle.after_restore.push(once_hook(hook, "after restore"));
+        self
+    }
+
+    /// Run a function before the handler is called for every invocation.
+    pub fn before_invoke<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Context) + Send + Sync + 'static,
+    {
+        self.invocation.before.push(Arc::new(hook));
+        self
+    }
+
+    /// Run a function after the handler completes for every invocation, with the outcome
+    /// of the invocation. The outcome is an error when the handler fails, and when its response
+    /// can't be sent, for example because it exceeds the size limit. The function is called
+    /// before the response is sent to Lambda.
+    pub fn after_invoke<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Context, Result<(), &Diagnostic>) + Send + Sync + 'static,
+    {
+        self.invocation.after.push(Arc::new(hook));
+        self
+    }
+
+    /// Run a function when Lambda shuts down the execution environment.
+    ///
+    /// When at least one shutdown hook is registered, the runtime calls
+    /// [spawn_graceful_shutdown_handler](crate::spawn_graceful_shutdown_handler) after the `on_init`
+    /// hooks, which registers a no-op extension to receive the shutdown signals. In SnapStart mode,
+    /// the extension is registered before the `before_checkpoint` hooks, so that it's part of the snapshot.
+    #[cfg(all(unix, feature = "graceful-shutdown"))]
+    #[cfg_attr(docsrs, doc(cfg(all(unix, feature = "graceful-shutdown"))))]
+    pub fn on_shutdown<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.lifecycle.shutdown.push(Box::new(move || Box::pin(hook())));
+        self
+    }
+}
+
+/// Box a hook that runs once, logging its error before converting it into a [Diagnostic].
+fn once_hook<F, Fut, E>(hook: F, stage: &'static str) -> OnceHook
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), E>> + Send + 'static,
+    E: Into<Diagnostic> + Debug,
+{
+    Box::new(move || {
+        Box::pin(async move {
+            hook().await.map_err(|err| {
+                tracing::error!(error = ?err, "{stage} hook failed");
+                err.into()
+            })
+        })
+    })
+}
+
+/// Run hooks in order, and stop at the first one that fails.
+async fn run_once_hooks(hooks: Vec<OnceHook>) -> Result<(), Diagnostic> {
+    for hook in hooks {
+        hook().await?;
+    }
+    Ok(())
+}
+
+/// Hooks that run once in the lifecycle of the runtime.
+#[derive(Default)]
+pub(crate) struct LifecycleHooks {
+    init: Vec<OnceHook>,
+    before_checkpoint: Vec<OnceHook>,
+    after_restore: Vec<OnceHook>,
+    #[cfg(all(unix, feature = "graceful-shutdown"))]
+    shutdown: Vec<ShutdownHook>,
+}
+
+impl LifecycleHooks {
+    /// Run the init hooks and register the shutdown hooks. Init failures are reported
+    /// to the Lambda Runtime API before being returned.
+    ///
+    /// In SnapStart mode, the checkpoint hooks run after the init hooks, then the runtime
+    /// waits until the execution environment is restored, and it runs the restore hooks.
+    /// The shutdown hooks are registered before the checkpoint hooks, since extensions
+    /// can only register during the init phase, before the snapshot is taken.
+    pub(crate) async fn start(self, client: &ApiClient, snap_start: bool) -> Result<(), BoxError> {
+        let mut init = run_once_hooks(self.init).await;
+
+        #[cfg(all(unix, feature = "graceful-shutdown"))]
+        if init.is_ok() && !self.shutdown.is_empty() {
+            let hooks = self.shutdown;
+            crate::spawn_graceful_shutdown_handler(move || async move {
+                for hook in hooks {
+                    hook().await;
+                }
+            })
+            .await;
+        }
+
+        if init.is_ok() && snap_start {
+            init = run_once_hooks(self.before_checkpoint).await;
+        }
+        if let Err(diagnostic) = init {
+            let err = hook_error(&diagnostic);
+            let req = InitErrorRequest::new(diagnostic).into_req()?;
+            client.call(req).await?;
+            return Err(err);
+        }
+
+        if snap_start {
+            tracing::debug!("waiting for the execution environment to be restored");
+            let res = client.call(RestoreNextRequest.into_req()?).await?;
+            if !res.status().is_success() {
+                return Err(format!(
+                    "unexpected status restoring the execution environment: {}",
+                    res.status()
+                )
+                .into());
+            }
+
+            if let Err(diagnostic) = run_once_hooks(self.after_restore).await {
+                let err = hook_error(&diagnostic);
+                let req = RestoreErrorRequest::new(diagnostic).into_req()?;
+                client.call(req).await?;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hook_error(diagnostic: &Diagnostic) -> BoxError {
+    format!("{}: {}", diagnostic.error_type, diagnostic.error_message).into()
+}
+
+/// Hooks that run around every invocation.
+#[derive(Clone, Default)]
+pub struct InvocationHooks {
+    before: Vec<BeforeInvokeHook>,
+    after: Vec<AfterInvokeHook>,
+}
+
+impl InvocationHooks {
+    pub(crate) fn before_invoke(&self, context: &Context) {
+        for hook in &self.before {
+            hook(context);
+        }
+    }
+
+    pub(crate) fn has_after_invoke(&self) -> bool {
+        !self.after.is_empty()
+    }
+
+    pub(crate) fn after_invoke(&self, context: &Context, result: Result<(), &Diagnostic>) {
+        for hook in &self.after {
+            hook(context, result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn invocation_hooks_order() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let hooks = Hooks::new()
+            .before_invoke({
+                let calls = calls.clone();
+                move |context| calls.lock().unwrap().push(format!("before {}", context.request_id))
+            })
+            .after_invoke({
+                let calls = calls.clone();
+                move |context, result| {
+                    let outcome = match result {
+                        Ok(()) => "ok".to_string(),
+                        Err(diagnostic) => diagnostic.error_message.clone(),
+                    };
+                    calls
+                        .lock()
+                        .unwrap()
+                        .push(format!("after {} {outcome}", context.request_id))
+                }
+            })
+            .after_invoke({
+                let calls = calls.clone();
+                move |_, _| calls.lock().unwrap().push("flush".to_string())
+            });
+
+        let context = Context {
+            request_id: "id".to_string(),
+            ..Default::default()
+        };
+        let diagnostic = Diagnostic::from("boom");
+        hooks.invocation.before_invoke(&context);
+        hooks.invocation.after_invoke(&context, Ok(()));
+        hooks.invocation.after_invoke(&context, Err(&diagnostic));
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["before id", "after id ok", "flush", "after id boom", "flush"]
+        );
+    }
+}
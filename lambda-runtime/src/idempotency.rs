@@ -0,0 +1,371 @@
+//! Idempotent processing of the events delivered more than once by at-least-once sources,
+//! like SQS, SNS or EventBridge.
+//!
+//! The [IdempotencyLayer](crate::layers::IdempotencyLayer) derives an idempotency key from every event
+//! with an [IdempotencyKey] extractor, and records the progress of the invocation in an
+//! [IdempotencyStore]. When the same key is received again, the handler is not called: the layer
+//! returns the response recorded for the first invocation, or fails if that invocation is still running.
+use crate::Error;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+/// Status of an idempotency record.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum IdempotencyStatus {
+    /// An invocation with the key is running.
+    InProgress,
+    /// An invocation with the key completed, and its response is recorded.
+    Completed,
+    /// The record expired, and the key can be processed again.
+    Expired,
+}
+
+/// Record of the invocation that processed an idempotency key.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct IdempotencyRecord {
+    /// The idempotency key.
+    pub key: String,
+    /// The status of the invocation, either [IdempotencyStatus::InProgress] or [IdempotencyStatus::Completed].
+    /// Use [IdempotencyRecord::status_at] to take the expiration of the record into account.
+    pub status: IdempotencyStatus,
+    /// When the record expires, and the key can be processed again.
+    pub expires_at: SystemTime,
+    /// When an in-progress record expires. It's the deadline of the invocation, or at most
+    /// 15 minutes when the invocation has no deadline, so the key can be retried when the
+    /// invocation times out without completing.
+    pub in_progress_expires_at: Option<SystemTime>,
+    /// The response of the completed invocation, serialized as JSON.
+    pub response: Option<Value>,
+}
+
+impl IdempotencyRecord {
+    /// Status of the record at the given time.
+    pub fn status_at(&self, now: SystemTime) -> IdempotencyStatus {
+        let in_progress_expired = self.status == IdempotencyStatus::InProgress
+            && self.in_progress_expires_at.is_some_and(|expiry| expiry <= now);
+        if self.expires_at <= now || in_progress_expired {
+            IdempotencyStatus::Expired
+        } else {
+            self.status
+        }
+    }
+}
+
+/// Persistence layer for [IdempotencyRecord]s.
+///
+/// [IdempotencyStore::put_in_progress] must be atomic: when several invocations with the same key
+/// run at the same time, only one of them can create its record. Use a conditional write in stores
+/// backed by a database.
+pub trait IdempotencyStore: Send + Sync + 'static {
+    /// Save a new in-progress record, unless a record that's not [expired](IdempotencyStatus::Expired)
+    /// already exists for the same key. Returns the existing record in that case.
+    fn put_in_progress(&self, record: IdempotencyRecord) -> BoxFuture<'_, Result<Option<IdempotencyRecord>, Error>>;
+
+    /// Replace the record of a key, to mark it as completed.
+    fn update(&self, record: IdempotencyRecord) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Delete the record of a key, so the key can be processed again.
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+impl<S: IdempotencyStore + ?Sized> IdempotencyStore for Arc<S> {
+    fn put_in_progress(&self, record: IdempotencyRecord) -> BoxFuture<'_, Result<Option<IdempotencyRecord>, Error>> {
+        (**self).put_in_progress(record)
+    }
+
+    fn update(&self, record: IdempotencyRecord) -> BoxFuture<'_, Result<(), Error>> {
+        (**self).update(record)
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        (**self).delete(key)
+    }
+}
+
+/// Extractor of the idempotency key of an event payload.
+///
+/// It's implemented for closures that take a reference to the payload, and for [JsonPointer].
+/// Events without a key are processed without idempotency.
+pub trait IdempotencyKey<T> {
+    /// Get the idempotency key of a payload.
+    fn key(&self, payload: &T) -> Option<String>;
+}
+
+impl<T, F> IdempotencyKey<T> for F
+where
+    F: Fn(&T) -> Option<String>,
+{
+    fn key(&self, payload: &T) -> Option<String> {
+        self(payload)
+    }
+}
+
+/// Extract the idempotency key from the value at a [JSON pointer](https://datatracker.ietf.org/doc/html/rfc6901)
+/// of the serialized payload. String values are used as they are, other values are serialized as JSON.
+///
+/// # Example
+/// ```
+/// use lambda_runtime::idempotency::{IdempotencyKey, JsonPointer};
+/// use serde_json::json;
+///
+/// let key = JsonPointer::new("/detail/orderId");
+/// let event = json!({ "detail": { "orderId": "ord-1234" } });
+/// assert_eq!(key.key(&event).as_deref(), Some("ord-1234"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct JsonPointer {
+    pointer: String,
+}
+
+impl JsonPointer {
+    /// Create a new extractor for a JSON pointer, like `/detail/orderId`.
+    pub fn new(pointer: impl Into<String>) -> Self {
+        Self {
+            pointer: pointer.into(),
+        }
+    }
+}
+
+impl<T: Serialize> IdempotencyKey<T> for JsonPointer {
+    fn key(&self, payload: &T) -> Option<String> {
+        let payload = serde_json::to_value(payload).ok()?;
+        match payload.pointer(&self.pointer)? {
+            Value::Null => None,
+            Value::String(key) => Some(key.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+}
+
+/// [IdempotencyStore] that keeps the records in memory.
+///
+/// Records are lost when the execution environment shuts down, and they're not shared
+/// with other execution environments, so this store is only suitable for tests.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    records: Mutex<HashMap<String, IdempotencyRecord>>,
+}
+
+impl InMemoryStore {
+    /// Create a new empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the record of a key.
+    pub fn get(&self, key: &str) -> Option<IdempotencyRecord> {
+        self.records
+            .lock()
+            .expect("idempotency store poisoned")
+            .get(key)
+            .cloned()
+    }
+}
+
+impl IdempotencyStore for InMemoryStore {
+    fn put_in_progress(&self, record: IdempotencyRecord) -> BoxFuture<'_, Result<Option<IdempotencyRecord>, Error>> {
+        let mut records = self.records.lock().expect("idempotency store poisoned");
+        let existing = match records.get(&record.key) {
+            Some(existing) if existing.status_at(SystemTime::now()) != IdempotencyStatus::Expired => {
+                Some(existing.clone())
+            }
+            _ => {
+                records.insert(record.key.clone(), record);
+                None
+            }
+        };
+        Box::pin(futures::future::ready(Ok(existing)))
+    }
+
+    fn update(&self, record: IdempotencyRecord) -> BoxFuture<'_, Result<(), Error>> {
+        let mut records = self.records.lock().expect("idempotency store poisoned");
+        records.insert(record.key.clone(), record);
+        Box::pin(futures::future::ready(Ok(())))
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        self.records.lock().expect("idempotency store poisoned").remove(key);
+        Box::pin(futures::future::ready(Ok(())))
+    }
+}
+
+/// [IdempotencyStore] that keeps each record in a JSON file of a local directory.
+///
+/// The store uses blocking file operations and it's only atomic within a single process,
+/// so it's only suitable for tests and local development.
+#[derive(Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileStore {
+    /// Create a store that keeps its records in the given directory, creating it if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Get the record of a key.
+    pub fn get(&self, key: &str) -> Result<Option<IdempotencyRecord>, Error> {
+        match fs::read(self.path(key)) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Keys can contain any character and be of any length, so file names are the
+    /// SHA-256 hashes of the keys encoded in hexadecimal.
+    fn path(&self, key: &str) -> PathBuf {
+        let name: String = Sha256::digest(key).iter().map(|byte| format!("{byte:02x}")).collect();
+        self.dir.join(format!("{name}.json"))
+    }
+
+    fn write(&self, record: &IdempotencyRecord) -> Result<(), Error> {
+        fs::write(self.path(&record.key), serde_json::to_vec(record)?)?;
+        Ok(())
+    }
+}
+
+impl IdempotencyStore for FileStore {
+    fn put_in_progress(&self, record: IdempotencyRecord) -> BoxFuture<'_, Result<Option<IdempotencyRecord>, Error>> {
+        let _lock = self.lock.lock().expect("idempotency store poisoned");
+        let result = self.get(&record.key).and_then(|existing| match existing {
+            Some(existing) if existing.status_at(SystemTime::now()) != IdempotencyStatus::Expired => Ok(Some(existing)),
+            _ => self.write(&record).map(|_| None),
+        });
+        Box::pin(futures::future::ready(result))
+    }
+
+    fn update(&self, record: IdempotencyRecord) -> BoxFuture<'_, Result<(), Error>> {
+        let _lock = self.lock.lock().expect("idempotency store poisoned");
+        Box::pin(futures::future::ready(self.write(&record)))
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        let _lock = self.lock.lock().expect("idempotency store poisoned");
+        let result = match fs::remove_file(self.path(key)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        };
+        Box::pin(futures::future::ready(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn in_progress(key: &str, ttl: Duration, in_progress_ttl: Duration) -> IdempotencyRecord {
+        let now = SystemTime::now();
+        IdempotencyRecord {
+            key: key.into(),
+            status: IdempotencyStatus::InProgress,
+            expires_at: now + ttl,
+            in_progress_expires_at: Some(now + in_progress_ttl),
+            response: None,
+        }
+    }
+
+    #[test]
+    fn record_status() {
+        let now = SystemTime::now();
+        let record = in_progress("key", Duration::from_secs(60), Duration::from_secs(5));
+        assert_eq!(record.status_at(now), IdempotencyStatus::InProgress);
+        assert_eq!(
+            record.status_at(now + Duration::from_secs(10)),
+            IdempotencyStatus::Expired
+        );
+
+        let completed = IdempotencyRecord {
+            status: IdempotencyStatus::Completed,
+            ..record
+        };
+        assert_eq!(
+            completed.status_at(now + Duration::from_secs(10)),
+            IdempotencyStatus::Completed
+        );
+        assert_eq!(
+            completed.status_at(now + Duration::from_secs(61)),
+            IdempotencyStatus::Expired
+        );
+    }
+
+    #[test]
+    fn json_pointer_key() {
+        let event = json!({"detail": {"orderId": "ord-1", "line": 2, "missing": null}});
+        assert_eq!(
+            JsonPointer::new("/detail/orderId").key(&event).as_deref(),
+            Some("ord-1")
+        );
+        assert_eq!(
+            JsonPointer::new("/detail").key(&event).as_deref(),
+            Some(r#"{"line":2,"missing":null,"orderId":"ord-1"}"#)
+        );
+        assert_eq!(JsonPointer::new("/detail/missing").key(&event), None);
+        assert_eq!(JsonPointer::new("/detail/unknown").key(&event), None);
+    }
+
+    async fn store_semantics(store: &dyn IdempotencyStore) {
+        let record = in_progress("order:1", Duration::from_secs(60), Duration::from_secs(60));
+        assert!(store.put_in_progress(record.clone()).await.unwrap().is_none());
+        assert_eq!(
+            store.put_in_progress(record.clone()).await.unwrap(),
+            Some(record.clone())
+        );
+
+        store.delete("order:1").await.unwrap();
+        assert!(store.put_in_progress(record.clone()).await.unwrap().is_none());
+
+        let expired = in_progress("order:2", Duration::from_secs(60), Duration::ZERO);
+        assert!(store.put_in_progress(expired.clone()).await.unwrap().is_none());
+        let retry = in_progress("order:2", Duration::from_secs(60), Duration::from_secs(60));
+        assert!(store.put_in_progress(retry).await.unwrap().is_none());
+
+        let completed = IdempotencyRecord {
+            status: IdempotencyStatus::Completed,
+            response: Some(json!({"ok": true})),
+            ..record
+        };
+        store.update(completed.clone()).await.unwrap();
+        let existing = store.put_in_progress(completed.clone()).await.unwrap();
+        assert_eq!(existing, Some(completed));
+    }
+
+    #[tokio::test]
+    async fn in_memory_store() {
+        store_semantics(&InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn file_store() {
+        let dir = std::env::temp_dir().join(format!("lambda-idempotency-{}", std::process::id()));
+        let store = FileStore::new(&dir).unwrap();
+        store_semantics(&store).await;
+        assert!(store.get("order:1").unwrap().is_some());
+
+        // File names have the same length whatever the length of the key.
+        let long_key = "order:".repeat(100);
+        let record = in_progress(&long_key, Duration::from_secs(60), Duration::from_secs(5));
+        assert_eq!(store.put_in_progress(record.clone()).await.unwrap(), None);
+        assert_eq!(store.get(&long_key).unwrap(), Some(record));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
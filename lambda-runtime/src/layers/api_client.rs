@@ -13,6 +13,7 @@ use tracing::error;
 /// This type is only meant for internal use in the Lambda runtime crate. It neither augments the
 /// inner service's request type nor its error type. However, this service returns an empty
 /// response `()` as the Lambda request has been completed.
+#[derive(Clone)]
 pub struct RuntimeApiClientService<S> {
     inner: S,
     client: Arc<Client>,
@@ -1,14 +1,17 @@
 use crate::{
-    deserializer,
+    codec::{JsonCodec, PayloadCodec},
+    diagnostic::type_name_of_val,
+    hooks::InvocationHooks,
     requests::{EventCompletionRequest, IntoRequest},
+    response_size::{GuardOutcome, GuardedRequest, ResponseSizeGuard},
     runtime::LambdaInvocation,
-    Diagnostic, EventErrorRequest, IntoFunctionResponse, LambdaEvent,
+    Context, Diagnostic, EventErrorRequest, FunctionResponse, IntoFunctionResponse, LambdaEvent,
 };
-use futures::{ready, Stream};
+use bytes::Bytes;
+use futures::{future::BoxFuture, ready, Stream};
 use lambda_runtime_api_client::{body::Body, BoxError};
 use pin_project::pin_project;
-use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, future::Future, marker::PhantomData, pin::Pin, task};
+use std::{fmt::Debug, future::Future, marker::PhantomData, pin::Pin, sync::Arc, task};
 use tower::Service;
 use tracing::{error, trace};
 
@@ -28,8 +31,13 @@ pub struct RuntimeApiResponseService<
     StreamingResponse,
     StreamItem,
     StreamError,
+    C = JsonCodec,
 > {
     inner: S,
+    codec: C,
+    xray_error_cause: bool,
+    hooks: Arc<InvocationHooks>,
+    response_size_guard: Arc<ResponseSizeGuard>,
     _phantom: PhantomData<(
         EventPayload,
         Response,
@@ -44,14 +52,82 @@ impl<S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem,
     RuntimeApiResponseService<S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>
 {
     pub fn new(inner: S) -> Self {
+        Self::with_codec(inner, JsonCodec)
+    }
+}
+
+impl<S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, C>
+    RuntimeApiResponseService<
+        S,
+        EventPayload,
+        Response,
+        BufferedResponse,
+        StreamingResponse,
+        StreamItem,
+        StreamError,
+        C,
+    >
+{
+    /// Create a new service that decodes events and encodes responses with the given codec.
+    pub fn with_codec(inner: S, codec: C) -> Self {
         Self {
             inner,
+            codec,
+            xray_error_cause: false,
+            hooks: Default::default(),
+            response_size_guard: Default::default(),
             _phantom: PhantomData,
         }
     }
+
+    /// Send the `Lambda-Runtime-Function-XRay-Error-Cause` header with the error responses
+    /// of invocations that are traced with X-Ray.
+    pub(crate) fn with_xray_error_cause(self, xray_error_cause: bool) -> Self {
+        Self {
+            xray_error_cause,
+            ..self
+        }
+    }
+
+    /// Run the given hooks before and after every call to the inner service.
+    pub(crate) fn with_hooks(self, hooks: Arc<InvocationHooks>) -> Self {
+        Self { hooks, ..self }
+    }
+
+    /// Check the size of the buffered responses with the given guard before sending them.
+    pub(crate) fn with_response_size_guard(self, response_size_guard: Arc<ResponseSizeGuard>) -> Self {
+        Self {
+            response_size_guard,
+            ..self
+        }
+    }
+}
+
+impl<S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, C> Clone
+    for RuntimeApiResponseService<
+        S,
+        EventPayload,
+        Response,
+        BufferedResponse,
+        StreamingResponse,
+        StreamItem,
+        StreamError,
+        C,
+    >
+where
+    S: Clone,
+    C: Clone,
+{
+    fn clone(&self) -> Self {
+        Self::with_codec(self.inner.clone(), self.codec.clone())
+            .with_xray_error_cause(self.xray_error_cause)
+            .with_hooks(self.hooks.clone())
+            .with_response_size_guard(self.response_size_guard.clone())
+    }
 }
 
-impl<S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError> Service<LambdaInvocation>
+impl<S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, C>
+    Service<LambdaInvocation>
     for RuntimeApiResponseService<
         S,
         EventPayload,
@@ -60,20 +136,28 @@ impl<S, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem,
         StreamingResponse,
         StreamItem,
         StreamError,
+        C,
     >
 where
     S: Service<LambdaEvent<EventPayload>, Response = Response, Error = Diagnostic>,
-    EventPayload: for<'de> Deserialize<'de>,
+    C: PayloadCodec<EventPayload, BufferedResponse> + Clone,
     Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
-    BufferedResponse: Serialize,
     StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
     StreamItem: Into<bytes::Bytes> + Send,
     StreamError: Into<BoxError> + Send + Debug,
 {
     type Response = http::Request<Body>;
     type Error = BoxError;
-    type Future =
-        RuntimeApiResponseFuture<S::Future, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>;
+    type Future = RuntimeApiResponseFuture<
+        S::Future,
+        EventPayload,
+        Response,
+        BufferedResponse,
+        StreamingResponse,
+        StreamItem,
+        StreamError,
+        C,
+    >;
 
     fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
         self.inner
@@ -102,9 +186,10 @@ where
         };
 
         let request_id = req.context.request_id.clone();
-        let lambda_event = match deserializer::deserialize::<EventPayload>(&req.body, req.context) {
-            Ok(lambda_event) => lambda_event,
-            Err(err) => match build_event_error_request(&request_id, err) {
+        let xray_error_cause = self.xray_error_cause && req.context.xray_trace_id.is_some();
+        let lambda_event = match self.codec.decode(req.body) {
+            Ok(payload) => LambdaEvent::new(payload, req.context),
+            Err(err) => match build_event_error_request(&self.codec, &request_id, err, xray_error_cause) {
                 Ok(request) => return RuntimeApiResponseFuture::Ready(Box::new(Some(Ok(request)))),
                 Err(err) => {
                     error!(error = ?err, "failed to build error response for Lambda Runtime API");
@@ -113,28 +198,103 @@ where
             },
         };
 
+        self.hooks.before_invoke(&lambda_event.context);
+        let after_invoke = self
+            .hooks
+            .has_after_invoke()
+            .then(|| (self.hooks.clone(), Box::new(lambda_event.context.clone())));
+
         // Once the handler input has been generated successfully, pass it through to inner services
         // allowing processing both before reaching the handler function and after the handler completes.
         let fut = self.inner.call(lambda_event);
-        RuntimeApiResponseFuture::Future(fut, request_id, PhantomData)
+        RuntimeApiResponseFuture::Future(
+            fut,
+            request_id,
+            xray_error_cause,
+            self.codec.clone(),
+            after_invoke,
+            self.response_size_guard.clone(),
+            PhantomData,
+        )
     }
 }
 
-fn build_event_error_request<T>(request_id: &str, err: T) -> Result<http::Request<Body>, BoxError>
+fn build_event_error_request<C, EventPayload, BufferedResponse>(
+    codec: &C,
+    request_id: &str,
+    err: C::DecodeError,
+    xray_error_cause: bool,
+) -> Result<http::Request<Body>, BoxError>
 where
-    T: Into<Diagnostic> + Debug,
+    C: PayloadCodec<EventPayload, BufferedResponse>,
 {
     error!(error = ?err, "Request payload deserialization into LambdaEvent<T> failed. The handler will not be called. Log at TRACE level to see the payload.");
-    EventErrorRequest::new(request_id, err).into_req()
+    EventErrorRequest::new(request_id, codec.decode_error(err))
+        .with_xray_error_cause(xray_error_cause)
+        .into_req()
+}
+
+/// Encode the buffered response of a handler with the codec, leaving streaming responses untouched.
+fn encode_response<C, EventPayload, Response, BufferedResponse, StreamingResponse>(
+    codec: &C,
+    response: Response,
+) -> Result<FunctionResponse<Bytes, StreamingResponse>, BoxError>
+where
+    C: PayloadCodec<EventPayload, BufferedResponse>,
+    Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
+{
+    Ok(match response.into_response() {
+        FunctionResponse::BufferedResponse(body) => FunctionResponse::BufferedResponse(codec.encode(body)?),
+        FunctionResponse::StreamingResponse(stream) => FunctionResponse::StreamingResponse(stream),
+    })
+}
+
+/// Run the after invoke hooks with the final outcome of an invocation,
+/// and build the request that reports it to the Lambda Runtime API.
+fn complete(
+    after_invoke: Option<(Arc<InvocationHooks>, Box<Context>)>,
+    request_id: &str,
+    xray_error_cause: bool,
+    outcome: GuardOutcome,
+) -> Result<http::Request<Body>, BoxError> {
+    match outcome {
+        GuardOutcome::Complete(request) => {
+            if let Some((hooks, context)) = after_invoke {
+                hooks.after_invoke(&context, Ok(()));
+            }
+            request
+        }
+        GuardOutcome::Fail(diagnostic) => {
+            if let Some((hooks, context)) = after_invoke {
+                hooks.after_invoke(&context, Err(&diagnostic));
+            }
+            EventErrorRequest::new(request_id, diagnostic)
+                .with_xray_error_cause(xray_error_cause)
+                .into_req()
+        }
+    }
 }
 
 #[pin_project(project = RuntimeApiResponseFutureProj)]
-pub enum RuntimeApiResponseFuture<F, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError> {
+pub enum RuntimeApiResponseFuture<
+    F,
+    EventPayload,
+    Response,
+    BufferedResponse,
+    StreamingResponse,
+    StreamItem,
+    StreamError,
+    C,
+> {
     Future(
         #[pin] F,
         String,
+        bool,
+        C,
+        Option<(Arc<InvocationHooks>, Box<Context>)>,
+        Arc<ResponseSizeGuard>,
         PhantomData<(
-            (),
+            EventPayload,
             Response,
             BufferedResponse,
             StreamingResponse,
@@ -146,14 +306,25 @@ pub enum RuntimeApiResponseFuture<F, Response, BufferedResponse, StreamingRespon
     /// We box it to avoid bloating the size of the more likely variant, which is
     /// the future that drives event processing.
     Ready(Box<Option<Result<http::Request<Body>, BoxError>>>),
+    /// This variant is used while an oversized response is offloaded by the [ResponseSizeGuard].
+    Offload(BoxFuture<'static, Result<http::Request<Body>, BoxError>>),
 }
 
-impl<F, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError> Future
-    for RuntimeApiResponseFuture<F, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>
+impl<F, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, C> Future
+    for RuntimeApiResponseFuture<
+        F,
+        EventPayload,
+        Response,
+        BufferedResponse,
+        StreamingResponse,
+        StreamItem,
+        StreamError,
+        C,
+    >
 where
     F: Future<Output = Result<Response, Diagnostic>>,
+    C: PayloadCodec<EventPayload, BufferedResponse>,
     Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
-    BufferedResponse: Serialize,
     StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
     StreamItem: Into<bytes::Bytes> + Send,
     StreamError: Into<BoxError> + Send + Debug,
@@ -161,12 +332,49 @@ where
     type Output = Result<http::Request<Body>, BoxError>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
-        task::Poll::Ready(match self.as_mut().project() {
-            RuntimeApiResponseFutureProj::Future(fut, request_id, _) => match ready!(fut.poll(cx)) {
-                Ok(ok) => EventCompletionRequest::new(request_id, ok).into_req(),
-                Err(err) => EventErrorRequest::new(request_id, err).into_req(),
-            },
-            RuntimeApiResponseFutureProj::Ready(ready) => ready.take().expect("future polled after completion"),
-        })
+        let offload = match self.as_mut().project() {
+            RuntimeApiResponseFutureProj::Future(
+                fut,
+                request_id,
+                xray_error_cause,
+                codec,
+                after_invoke,
+                response_size_guard,
+                _,
+            ) => {
+                let result = ready!(fut.poll(cx));
+                let guarded = match result.map(|ok| encode_response(codec, ok)) {
+                    Ok(Ok(FunctionResponse::BufferedResponse(body))) => response_size_guard.guard(request_id, body),
+                    Ok(Ok(streaming)) => GuardedRequest::Ready(GuardOutcome::Complete(
+                        EventCompletionRequest::new(request_id, streaming).into_req(),
+                    )),
+                    Ok(Err(err)) => {
+                        if let Some((hooks, context)) = after_invoke.take() {
+                            let diagnostic = Diagnostic::new(type_name_of_val(&err), err.to_string());
+                            hooks.after_invoke(&context, Err(&diagnostic));
+                        }
+                        return task::Poll::Ready(Err(err));
+                    }
+                    Err(diagnostic) => GuardedRequest::Ready(GuardOutcome::Fail(diagnostic)),
+                };
+                let after_invoke = after_invoke.take();
+                match guarded {
+                    GuardedRequest::Ready(outcome) => {
+                        return task::Poll::Ready(complete(after_invoke, request_id, *xray_error_cause, outcome))
+                    }
+                    GuardedRequest::Offload(offload) => {
+                        let request_id = request_id.clone();
+                        let xray_error_cause = *xray_error_cause;
+                        Box::pin(async move { complete(after_invoke, &request_id, xray_error_cause, offload.await) })
+                    }
+                }
+            }
+            RuntimeApiResponseFutureProj::Ready(ready) => {
+                return task::Poll::Ready(ready.take().expect("future polled after completion"))
+            }
+            RuntimeApiResponseFutureProj::Offload(offload) => return offload.as_mut().poll(cx),
+        };
+        self.set(RuntimeApiResponseFuture::Offload(offload));
+        self.poll(cx)
     }
 }
@@ -0,0 +1,348 @@
+use crate::{
+    idempotency::{IdempotencyKey, IdempotencyRecord, IdempotencyStatus, IdempotencyStore},
+    Diagnostic, LambdaEvent,
+};
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    sync::Arc,
+    task,
+    time::{Duration, SystemTime},
+};
+use tower::{Layer, Service};
+use tracing::{error, warn};
+
+/// Error type reported to Lambda when an event is received while another invocation is processing it.
+const IN_PROGRESS_ERROR_TYPE: &str = "Runtime.IdempotencyInProgress";
+
+/// Error type reported to Lambda when the idempotency store fails.
+const STORE_ERROR_TYPE: &str = "Runtime.IdempotencyStoreError";
+
+/// Default time during which a completed invocation is not processed again.
+const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Longest time an invocation can run, used as the in-progress expiry of the invocations
+/// without a deadline in the future.
+const MAX_INVOCATION_DURATION: Duration = Duration::from_secs(15 * 60);
+
+/// Tower middleware to process every event only once, based on an idempotency key derived from its payload.
+///
+/// Before calling the handler, the layer saves an in-progress record for the key in an [IdempotencyStore].
+/// When the handler succeeds, the record is marked as completed with the response, and the events with the
+/// same key received before the record expires get that response without calling the handler.
+/// When the handler fails, the record is deleted so the event can be retried.
+///
+/// An event received while the invocation with the same key is still running fails with a [Diagnostic]
+/// which `error_type` is `Runtime.IdempotencyInProgress`. In-progress records expire at the
+/// [deadline](crate::Context::deadline) of their invocation, so a key can be processed again
+/// when an invocation times out. When the deadline is missing or already past, they expire after
+/// the TTL or 15 minutes, the maximum duration of an invocation, whichever is shorter.
+///
+/// Events without an idempotency key are processed without idempotency.
+///
+/// The layer wraps the handler function, so it must be applied before giving the handler
+/// to the runtime.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{
+///     idempotency::{InMemoryStore, JsonPointer},
+///     layers::IdempotencyLayer,
+///     Error, LambdaEvent,
+/// };
+/// use serde_json::Value;
+/// use std::time::Duration;
+/// use tower::{service_fn, ServiceBuilder};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let func = ServiceBuilder::new()
+///         .layer(
+///             IdempotencyLayer::new(InMemoryStore::new(), JsonPointer::new("/detail/orderId"))
+///                 .with_ttl(Duration::from_secs(15 * 60)),
+///         )
+///         .service(service_fn(func));
+///     lambda_runtime::run(func).await
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+pub struct IdempotencyLayer<St, K> {
+    store: Arc<St>,
+    key: K,
+    ttl: Duration,
+}
+
+impl<St, K> IdempotencyLayer<St, K> {
+    /// Create a new idempotency layer that keeps its records in `store`,
+    /// and gets the idempotency key of each event with `key`.
+    pub fn new(store: St, key: K) -> Self {
+        Self {
+            store: Arc::new(store),
+            key,
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// Set how long a completed invocation is remembered. Defaults to one hour.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+}
+
+impl<S, St, K> Layer<S> for IdempotencyLayer<St, K>
+where
+    K: Clone,
+{
+    type Service = IdempotencyService<S, St, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IdempotencyService {
+            inner,
+            store: self.store.clone(),
+            key: self.key.clone(),
+            ttl: self.ttl,
+        }
+    }
+}
+
+/// Tower service returned by [IdempotencyLayer].
+pub struct IdempotencyService<S, St, K> {
+    inner: S,
+    store: Arc<St>,
+    key: K,
+    ttl: Duration,
+}
+
+impl<S: Clone, St, K: Clone> Clone for IdempotencyService<S, St, K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            store: self.store.clone(),
+            key: self.key.clone(),
+            ttl: self.ttl,
+        }
+    }
+}
+
+impl<S, St, K, Payload> Service<LambdaEvent<Payload>> for IdempotencyService<S, St, K>
+where
+    S: Service<LambdaEvent<Payload>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Response: Serialize + DeserializeOwned + Send,
+    S::Error: Into<Diagnostic>,
+    St: IdempotencyStore,
+    K: IdempotencyKey<Payload>,
+    Payload: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = Diagnostic;
+    type Future = BoxFuture<'static, Result<S::Response, Diagnostic>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: LambdaEvent<Payload>) -> Self::Future {
+        // Take the service that was ready, and leave a clone in its place for the next call.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let Some(key) = self.key.key(&req.payload) else {
+            warn!(requestId = %req.context.request_id, "event without idempotency key, processing it without idempotency");
+            let future = inner.call(req);
+            return Box::pin(async move { future.await.map_err(Into::into) });
+        };
+
+        let now = SystemTime::now();
+        let deadline = req.context.deadline();
+        let in_progress_expires_at = if deadline > now {
+            deadline
+        } else {
+            now + self.ttl.min(MAX_INVOCATION_DURATION)
+        };
+        let record = IdempotencyRecord {
+            key,
+            status: IdempotencyStatus::InProgress,
+            expires_at: now + self.ttl,
+            in_progress_expires_at: Some(in_progress_expires_at),
+            response: None,
+        };
+        let store = self.store.clone();
+
+        Box::pin(async move {
+            match store.put_in_progress(record.clone()).await {
+                Ok(None) => {}
+                Ok(Some(existing)) => return cached_response(existing),
+                Err(err) => return Err(store_error("failed to save the idempotency record", err)),
+            }
+
+            let result: Result<_, Diagnostic> = inner.call(req).await.map_err(Into::into);
+            match result {
+                Ok(response) => {
+                    let completed = serde_json::to_value(&response).map(|response| IdempotencyRecord {
+                        status: IdempotencyStatus::Completed,
+                        response: Some(response),
+                        ..record
+                    });
+                    let saved = match completed {
+                        Ok(completed) => store.update(completed).await,
+                        Err(err) => Err(err.into()),
+                    };
+                    // The handler already completed its side effects, so its response is returned anyway.
+                    if let Err(err) = saved {
+                        error!(error = ?err, "failed to save the idempotency record of a completed invocation");
+                    }
+                    Ok(response)
+                }
+                Err(diagnostic) => {
+                    if let Err(err) = store.delete(&record.key).await {
+                        error!(error = ?err, "failed to delete the idempotency record of a failed invocation");
+                    }
+                    Err(diagnostic)
+                }
+            }
+        })
+    }
+}
+
+/// Response to an event which key is already recorded.
+fn cached_response<R: DeserializeOwned>(existing: IdempotencyRecord) -> Result<R, Diagnostic> {
+    match (existing.status, existing.response) {
+        (IdempotencyStatus::Completed, Some(response)) => serde_json::from_value(response)
+            .map_err(|err| store_error("failed to deserialize the recorded response", err.into())),
+        _ => Err(Diagnostic {
+            error_type: IN_PROGRESS_ERROR_TYPE.into(),
+            error_message: format!(
+                "Event with idempotency key `{}` is already being processed",
+                existing.key
+            ),
+            ..Default::default()
+        }),
+    }
+}
+
+fn store_error(message: &str, err: crate::Error) -> Diagnostic {
+    error!(error = ?err, "{message}");
+    Diagnostic {
+        error_type: STORE_ERROR_TYPE.into(),
+        error_message: format!("{message}: {err}"),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        idempotency::{InMemoryStore, JsonPointer},
+        Context, Error,
+    };
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::Notify;
+    use tower::{service_fn, ServiceExt};
+
+    fn event(payload: Value) -> LambdaEvent<Value> {
+        let deadline = SystemTime::now() + Duration::from_secs(60);
+        let context = Context {
+            deadline: deadline.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as u64,
+            ..Default::default()
+        };
+        LambdaEvent::new(payload, context)
+    }
+
+    #[tokio::test]
+    async fn duplicates_get_the_recorded_response() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let store = Arc::new(InMemoryStore::new());
+        let service = IdempotencyLayer::new(store.clone(), JsonPointer::new("/orderId")).layer(service_fn({
+            let calls = calls.clone();
+            move |event: LambdaEvent<Value>| {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                async move { Ok::<_, Error>(json!({ "call": call, "orderId": event.payload["orderId"] })) }
+            }
+        }));
+
+        let first = service.clone().oneshot(event(json!({"orderId": "1"}))).await.unwrap();
+        let duplicate = service.clone().oneshot(event(json!({"orderId": "1"}))).await.unwrap();
+        let other = service.clone().oneshot(event(json!({"orderId": "2"}))).await.unwrap();
+        let without_key = service.oneshot(event(json!({}))).await.unwrap();
+
+        assert_eq!(first, json!({"call": 0, "orderId": "1"}));
+        assert_eq!(duplicate, first);
+        assert_eq!(other, json!({"call": 1, "orderId": "2"}));
+        assert_eq!(without_key["call"], 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(store.get("1").unwrap().status, IdempotencyStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn failed_invocations_are_retried() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let store = Arc::new(InMemoryStore::new());
+        let service = IdempotencyLayer::new(store.clone(), |payload: &Value| {
+            payload["id"].as_str().map(String::from)
+        })
+        .layer(service_fn({
+            let calls = calls.clone();
+            move |_: LambdaEvent<Value>| {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match call {
+                        0 => Err(Error::from("transient failure")),
+                        _ => Ok(call),
+                    }
+                }
+            }
+        }));
+
+        service.clone().oneshot(event(json!({"id": "a"}))).await.unwrap_err();
+        assert!(store.get("a").is_none());
+        assert_eq!(service.oneshot(event(json!({"id": "a"}))).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn in_progress_records_without_deadline_expire() {
+        let store = Arc::new(InMemoryStore::new());
+        let service = IdempotencyLayer::new(store.clone(), JsonPointer::new("/id")).layer(service_fn({
+            let store = store.clone();
+            move |_: LambdaEvent<Value>| {
+                let record = store.get("1").unwrap();
+                async move { Ok::<_, Error>(record.in_progress_expires_at) }
+            }
+        }));
+
+        let before = SystemTime::now();
+        let event = LambdaEvent::new(json!({"id": 1}), Context::default());
+        let expires_at = service.oneshot(event).await.unwrap().unwrap();
+        assert!(expires_at > before);
+        assert!(expires_at <= SystemTime::now() + MAX_INVOCATION_DURATION);
+    }
+
+    #[tokio::test]
+    async fn concurrent_duplicates_fail() {
+        let release = Arc::new(Notify::new());
+        let service = IdempotencyLayer::new(InMemoryStore::new(), JsonPointer::new("/id")).layer(service_fn({
+            let release = release.clone();
+            move |_: LambdaEvent<Value>| {
+                let release = release.clone();
+                async move {
+                    release.notified().await;
+                    Ok::<_, Error>(())
+                }
+            }
+        }));
+
+        let first = tokio::spawn(service.clone().oneshot(event(json!({"id": 1}))));
+        tokio::task::yield_now().await;
+        let duplicate = service.oneshot(event(json!({"id": 1}))).await.unwrap_err();
+        assert_eq!(duplicate.error_type, IN_PROGRESS_ERROR_TYPE);
+
+        release.notify_one();
+        first.await.unwrap().unwrap();
+    }
+}
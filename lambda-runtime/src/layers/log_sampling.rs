@@ -0,0 +1,264 @@
+use crate::LambdaInvocation;
+use lambda_runtime_api_client::{
+    tracing::{log_level_handle, LogLevelGuard},
+    BoxError,
+};
+use pin_project::pin_project;
+use serde::{de::IgnoredAny, Deserialize};
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::{BuildHasher, RandomState},
+    pin::Pin,
+    task,
+};
+use tower::{Layer, Service};
+use tracing::debug;
+
+/// Tower middleware to raise the log level to `DEBUG` for a sample of the invocations.
+///
+/// The layer raises the log level of the default subscriber, initialized with
+/// [init_default_subscriber](crate::tracing::init_default_subscriber), while an invocation is sampled,
+/// and restores the default level when the invocation completes. Invocations are sampled randomly
+/// with the configured rate, or when they carry a trigger header or client context key.
+///
+/// The log level is global, so it's raised for all the invocations in flight when the runtime
+/// processes several invocations at the same time.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{layers::LogSamplingLayer, tracing, Error, LambdaEvent, Runtime};
+/// use serde_json::Value;
+/// use tower::service_fn;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     tracing::init_default_subscriber();
+///     let runtime = Runtime::new(service_fn(func)).layer(
+///         LogSamplingLayer::new(0.01)
+///             .with_header("x-debug-logs")
+///             .with_client_context_key("debugLogs"),
+///     );
+///     runtime.run().await
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     tracing::debug!(payload = ?event.payload, "only logged for sampled invocations");
+///     Ok(event.payload)
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct LogSamplingLayer {
+    sampler: Sampler,
+}
+
+impl LogSamplingLayer {
+    /// Create a new layer that samples `sample_rate` of the invocations, between `0.0` and `1.0`.
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            sampler: Sampler {
+                sample_rate: sample_rate.clamp(0.0, 1.0),
+                header: None,
+                client_context_key: None,
+                hasher: RandomState::new(),
+            },
+        }
+    }
+
+    /// Sample the invocations that have this header. The header is looked up in the headers of
+    /// the invocation, and in the `headers` of HTTP events like API Gateway and Function URL requests.
+    pub fn with_header(mut self, header: impl Into<String>) -> Self {
+        self.sampler.header = Some(header.into());
+        self
+    }
+
+    /// Sample the invocations that have this key in the custom values of their
+    /// [client context](crate::Context::client_context).
+    pub fn with_client_context_key(mut self, key: impl Into<String>) -> Self {
+        self.sampler.client_context_key = Some(key.into());
+        self
+    }
+}
+
+impl<S> Layer<S> for LogSamplingLayer {
+    type Service = LogSamplingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LogSamplingService {
+            inner,
+            sampler: self.sampler.clone(),
+        }
+    }
+}
+
+/// Tower service returned by [LogSamplingLayer].
+#[derive(Clone)]
+pub struct LogSamplingService<S> {
+    inner: S,
+    sampler: Sampler,
+}
+
+impl<S> Service<LambdaInvocation> for LogSamplingService<S>
+where
+    S: Service<LambdaInvocation, Response = (), Error = BoxError>,
+{
+    type Response = ();
+    type Error = BoxError;
+    type Future = LogSamplingFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: LambdaInvocation) -> Self::Future {
+        let guard = match log_level_handle() {
+            Some(handle) if self.sampler.is_sampled(&req) => {
+                let guard = handle.raise_to_debug();
+                debug!(requestId = %req.context.request_id, "log level raised to DEBUG for this invocation");
+                Some(guard)
+            }
+            _ => None,
+        };
+
+        LogSamplingFuture {
+            future: self.inner.call(req),
+            guard,
+        }
+    }
+}
+
+/// Future returned by [LogSamplingService].
+#[pin_project]
+pub struct LogSamplingFuture<F> {
+    #[pin]
+    future: F,
+    guard: Option<LogLevelGuard>,
+}
+
+impl<F> Future for LogSamplingFuture<F>
+where
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
+        let this = self.project();
+        let output = task::ready!(this.future.poll(cx));
+        // Restore the default level as soon as the invocation completes.
+        this.guard.take();
+        task::Poll::Ready(output)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Sampler {
+    sample_rate: f64,
+    header: Option<String>,
+    client_context_key: Option<String>,
+    hasher: RandomState,
+}
+
+/// Header names of HTTP events, like API Gateway, ALB and Function URL requests.
+#[derive(Deserialize)]
+struct HttpEventHeaders {
+    #[serde(default)]
+    headers: HashMap<String, IgnoredAny>,
+}
+
+impl Sampler {
+    fn is_sampled(&self, req: &LambdaInvocation) -> bool {
+        // Parsing the payload is the most expensive check, it only runs when the others miss.
+        self.has_invocation_header(req)
+            || self.has_client_context_key(req)
+            || self.is_randomly_sampled(&req.context.request_id)
+            || self.has_http_event_header(req)
+    }
+
+    fn has_invocation_header(&self, req: &LambdaInvocation) -> bool {
+        match &self.header {
+            Some(header) => req.parts.headers.contains_key(header.as_str()),
+            None => false,
+        }
+    }
+
+    fn has_http_event_header(&self, req: &LambdaInvocation) -> bool {
+        let Some(header) = &self.header else {
+            return false;
+        };
+        serde_json::from_slice::<HttpEventHeaders>(&req.body)
+            .map(|event| event.headers.keys().any(|name| name.eq_ignore_ascii_case(header)))
+            .unwrap_or_default()
+    }
+
+    fn has_client_context_key(&self, req: &LambdaInvocation) -> bool {
+        match (&self.client_context_key, &req.context.client_context) {
+            (Some(key), Some(client_context)) => client_context.custom.contains_key(key),
+            _ => false,
+        }
+    }
+
+    /// Request ids are random, hashing them with a random key gives a uniform sample.
+    fn is_randomly_sampled(&self, request_id: &str) -> bool {
+        if self.sample_rate <= 0.0 {
+            return false;
+        }
+        let hash = self.hasher.hash_one(request_id);
+        (hash as f64 / u64::MAX as f64) < self.sample_rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{types::ClientContext, Context};
+    use bytes::Bytes;
+
+    fn invocation(body: &'static str, client_context: Option<ClientContext>) -> LambdaInvocation {
+        let (parts, _) = http::Response::new(()).into_parts();
+        LambdaInvocation {
+            parts,
+            body: Bytes::from_static(body.as_bytes()),
+            context: Context {
+                request_id: "8476a536-e9f4-11e8-9739-2dfe598c3fcd".into(),
+                client_context,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn sample_rate() {
+        let req = invocation("{}", None);
+        assert!(!LogSamplingLayer::new(0.0).sampler.is_sampled(&req));
+        assert!(LogSamplingLayer::new(1.0).sampler.is_sampled(&req));
+
+        let sampler = LogSamplingLayer::new(0.5).sampler;
+        let sampled = (0..1000)
+            .filter(|n| sampler.is_randomly_sampled(&format!("request-{n}")))
+            .count();
+        assert!((350..650).contains(&sampled), "{sampled}");
+    }
+
+    #[test]
+    fn sample_on_trigger() {
+        let sampler = LogSamplingLayer::new(0.0)
+            .with_header("x-debug-logs")
+            .with_client_context_key("debugLogs")
+            .sampler;
+
+        let http_event = invocation(r#"{"headers":{"X-Debug-Logs":"1"},"body":null}"#, None);
+        assert!(sampler.is_sampled(&http_event));
+        assert!(!sampler.is_sampled(&invocation(r#"{"headers":{"accept":"*/*"}}"#, None)));
+        assert!(!sampler.is_sampled(&invocation("[1, 2]", None)));
+
+        let mut invocation_header = invocation("[1, 2]", None);
+        invocation_header
+            .parts
+            .headers
+            .insert("x-debug-logs", http::HeaderValue::from_static("1"));
+        assert!(sampler.is_sampled(&invocation_header));
+
+        let client_context: ClientContext = serde_json::from_str(r#"{"custom":{"debugLogs":"true"}}"#).unwrap();
+        assert!(sampler.is_sampled(&invocation("{}", Some(client_context))));
+    }
+}
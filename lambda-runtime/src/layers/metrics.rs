@@ -0,0 +1,311 @@
+use crate::{
+    metrics::{Metrics, Unit},
+    LambdaEvent,
+};
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    io::{self, Write},
+    pin::Pin,
+    task,
+};
+use tower::{Layer, Service};
+use tracing::error;
+
+/// Tower middleware to publish the custom metrics of a Lambda function with the
+/// [CloudWatch Embedded Metric Format](https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html).
+///
+/// The layer configures the [Metrics] handle of each invocation with its namespace and default
+/// dimensions, and writes the metrics recorded by the handler as one EMF log line when the
+/// handler completes. Nothing is written when the handler doesn't record any metric.
+///
+/// When the `AWS_LAMBDA_LOG_FORMAT` environment variable is set to `JSON`, the log line also
+/// includes the `requestId` of the invocation, like the rest of the JSON log lines.
+///
+/// The layer wraps the handler function, so it must be applied before giving the handler
+/// to the runtime.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{layers::MetricsLayer, metrics::Unit, Error, LambdaEvent};
+/// use serde_json::Value;
+/// use tower::{service_fn, ServiceBuilder};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let func = ServiceBuilder::new()
+///         .layer(
+///             MetricsLayer::new("Orders")
+///                 .with_dimension("Service", "checkout")
+///                 .with_cold_start_metric()
+///                 .with_error_metric(),
+///         )
+///         .service(service_fn(func));
+///     lambda_runtime::run(func).await
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     event.context.metrics.add("OrdersCreated", 1.0, Unit::Count);
+///     Ok(event.payload)
+/// }
+/// ```
+pub struct MetricsLayer<W = fn() -> io::Stdout> {
+    namespace: String,
+    dimensions: Vec<(String, String)>,
+    cold_start: bool,
+    errors: bool,
+    make_writer: W,
+}
+
+impl MetricsLayer {
+    /// Create a new metrics layer that publishes metrics in the given CloudWatch namespace,
+    /// and writes them to STDOUT.
+    pub fn new(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            dimensions: Vec::new(),
+            cold_start: false,
+            errors: false,
+            make_writer: io::stdout,
+        }
+    }
+}
+
+impl<W> MetricsLayer<W> {
+    /// Add a dimension to the metrics of every invocation.
+    pub fn with_dimension(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.dimensions.push((name.into(), value.into()));
+        self
+    }
+
+    /// Record a `ColdStart` metric, with value `1`, in the invocations that are a
+    /// [cold start](crate::Context::is_cold_start).
+    pub fn with_cold_start_metric(mut self) -> Self {
+        self.cold_start = true;
+        self
+    }
+
+    /// Record an `Errors` metric in every invocation, with value `1` when the handler fails
+    /// and `0` otherwise, so the error rate is the average of the metric.
+    pub fn with_error_metric(mut self) -> Self {
+        self.errors = true;
+        self
+    }
+
+    /// Write the EMF log lines with a different writer than STDOUT.
+    ///
+    /// The function is called once for every log line. It can be the same writer given to
+    /// [init_default_subscriber_with_writer](crate::tracing::init_default_subscriber_with_writer),
+    /// so metrics and logs go through the same output.
+    pub fn with_writer<M, Wr>(self, make_writer: M) -> MetricsLayer<M>
+    where
+        M: Fn() -> Wr + Clone,
+        Wr: Write,
+    {
+        MetricsLayer {
+            namespace: self.namespace,
+            dimensions: self.dimensions,
+            cold_start: self.cold_start,
+            errors: self.errors,
+            make_writer,
+        }
+    }
+}
+
+impl<S, W> Layer<S> for MetricsLayer<W>
+where
+    W: Clone,
+{
+    type Service = MetricsService<S, W>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            namespace: self.namespace.clone(),
+            dimensions: self.dimensions.clone(),
+            cold_start: self.cold_start,
+            errors: self.errors,
+            make_writer: self.make_writer.clone(),
+        }
+    }
+}
+
+/// Tower service returned by [MetricsLayer].
+#[derive(Clone)]
+pub struct MetricsService<S, W> {
+    inner: S,
+    namespace: String,
+    dimensions: Vec<(String, String)>,
+    cold_start: bool,
+    errors: bool,
+    make_writer: W,
+}
+
+impl<S, W, Wr, Payload> Service<LambdaEvent<Payload>> for MetricsService<S, W>
+where
+    S: Service<LambdaEvent<Payload>>,
+    W: Fn() -> Wr + Clone,
+    Wr: Write,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = MetricsFuture<S::Future, W>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: LambdaEvent<Payload>) -> Self::Future {
+        let metrics = req.context.metrics.clone();
+        metrics.set_namespace(self.namespace.as_str());
+        for (name, value) in &self.dimensions {
+            metrics.add_dimension(name.as_str(), value.as_str());
+        }
+        if self.cold_start && req.context.is_cold_start() {
+            metrics.add("ColdStart", 1.0, Unit::Count);
+        }
+        if json_log_format() {
+            metrics.add_property("requestId", req.context.request_id.as_str());
+        }
+
+        MetricsFuture {
+            future: self.inner.call(req),
+            metrics,
+            errors: self.errors,
+            make_writer: self.make_writer.clone(),
+        }
+    }
+}
+
+/// Future returned by [MetricsService].
+#[pin_project]
+pub struct MetricsFuture<F, W> {
+    #[pin]
+    future: F,
+    metrics: Metrics,
+    errors: bool,
+    make_writer: W,
+}
+
+impl<F, W, Wr, T, E> Future for MetricsFuture<F, W>
+where
+    F: Future<Output = Result<T, E>>,
+    W: Fn() -> Wr,
+    Wr: Write,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
+        let this = self.project();
+        let result = task::ready!(this.future.poll(cx));
+
+        if *this.errors {
+            let value = if result.is_err() { 1.0 } else { 0.0 };
+            this.metrics.add("Errors", value, Unit::Count);
+        }
+        if let Some(document) = this.metrics.flush() {
+            let mut writer = (this.make_writer)();
+            if let Err(err) = writeln!(writer, "{document}") {
+                error!(error = ?err, "failed to write EMF metrics");
+            }
+        }
+        task::Poll::Ready(result)
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn json_log_format() -> bool {
+    use lambda_runtime_api_client::tracing::LogFormat;
+    LogFormat::from_env() == LogFormat::Json
+}
+
+#[cfg(not(feature = "tracing"))]
+fn json_log_format() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Context, Error};
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+    use tower::{service_fn, ServiceExt};
+
+    #[derive(Clone, Default)]
+    struct Lines(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Lines {
+        fn documents(&self) -> Vec<Value> {
+            let lines = self.0.lock().unwrap();
+            serde_json::Deserializer::from_slice(&lines)
+                .into_iter()
+                .map(Result::unwrap)
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn metrics_flushed_after_each_invocation() {
+        let lines = Lines::default();
+        let layer = MetricsLayer::new("Orders")
+            .with_dimension("Service", "checkout")
+            .with_cold_start_metric()
+            .with_error_metric()
+            .with_writer({
+                let lines = lines.clone();
+                move || lines.clone()
+            });
+        let service = layer.layer(service_fn(|event: LambdaEvent<bool>| async move {
+            event.context.metrics.add("Items", 2.0, Unit::Count);
+            if event.payload {
+                Ok(())
+            } else {
+                Err(Error::from("invalid order"))
+            }
+        }));
+
+        let cold_start = Context {
+            invocation_sequence: 1,
+            ..Default::default()
+        };
+        let ok = service.clone().oneshot(LambdaEvent::new(true, cold_start));
+        ok.await.unwrap();
+        let err = service.oneshot(LambdaEvent::new(false, Context::default()));
+        err.await.unwrap_err();
+
+        let documents = lines.documents();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0]["_aws"]["CloudWatchMetrics"][0]["Namespace"], "Orders");
+        assert_eq!(documents[0]["Service"], "checkout");
+        assert_eq!(documents[0]["ColdStart"], 1.0);
+        assert_eq!(documents[0]["Errors"], 0.0);
+        assert_eq!(documents[0]["Items"], 2.0);
+        assert!(documents[1].get("ColdStart").is_none());
+        assert_eq!(documents[1]["Errors"], 1.0);
+    }
+
+    #[tokio::test]
+    async fn nothing_written_without_metrics() {
+        let lines = Lines::default();
+        let service = MetricsLayer::new("Orders")
+            .with_writer({
+                let lines = lines.clone();
+                move || lines.clone()
+            })
+            .layer(service_fn(|_: LambdaEvent<()>| async { Ok::<_, Error>(()) }));
+
+        service.oneshot(LambdaEvent::new((), Context::default())).await.unwrap();
+        assert!(lines.documents().is_empty());
+    }
+}
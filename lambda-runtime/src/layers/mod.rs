@@ -4,13 +4,25 @@ mod api_response;
 mod panic;
 
 // Publicly available services.
+mod idempotency;
+mod metrics;
+mod timeout;
 mod trace;
 
 pub(crate) use api_client::RuntimeApiClientService;
 pub(crate) use api_response::RuntimeApiResponseService;
+pub use idempotency::IdempotencyLayer;
+pub use metrics::MetricsLayer;
 pub(crate) use panic::CatchPanicService;
+pub use timeout::TimeoutLayer;
 pub use trace::TracingLayer;
 
+#[cfg(feature = "tracing")]
+mod log_sampling;
+#[cfg(feature = "tracing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+pub use log_sampling::LogSamplingLayer;
+
 #[cfg(feature = "opentelemetry")]
 mod otel;
 #[cfg(feature = "opentelemetry")]
@@ -44,17 +44,16 @@ where
         OpenTelemetryService {
             inner,
             flush_fn: self.flush_fn.clone(),
-            coldstart: true,
             otel_attribute_trigger: self.otel_attribute_trigger.to_string(),
         }
     }
 }
 
 /// Tower service created by [OpenTelemetryLayer].
+#[derive(Clone)]
 pub struct OpenTelemetryService<S, F> {
     inner: S,
     flush_fn: F,
-    coldstart: bool,
     otel_attribute_trigger: String,
 }
 
@@ -78,12 +77,9 @@ where
             "otel.kind" = field::Empty,
             { attribute::FAAS_TRIGGER } = &self.otel_attribute_trigger,
             { attribute::FAAS_INVOCATION_ID } = req.context.request_id,
-            { attribute::FAAS_COLDSTART } = self.coldstart
+            { attribute::FAAS_COLDSTART } = req.context.is_cold_start()
         );
 
-        // After the first execution, we can set 'coldstart' to false
-        self.coldstart = false;
-
         let future = {
             // Enter the span before calling the inner service
             // to ensure that it's assigned as parent of the inner spans.
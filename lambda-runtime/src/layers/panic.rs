@@ -107,6 +107,7 @@ impl<F> CatchPanicFuture<'_, F> {
         Diagnostic {
             error_type: type_name_of_val(err),
             error_message,
+            ..Default::default()
         }
     }
 }
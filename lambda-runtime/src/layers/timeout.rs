@@ -0,0 +1,273 @@
+use crate::{Context, Diagnostic, LambdaEvent};
+use pin_project::pin_project;
+use std::{
+    fmt::Debug,
+    future::{Future, Ready},
+    pin::Pin,
+    task,
+    time::{Duration, SystemTime},
+};
+use tokio::time::{sleep, Sleep};
+use tower::{Layer, Service};
+use tracing::error;
+
+/// Error type reported to Lambda when a handler doesn't complete before the deadline.
+const HANDLER_TIMEOUT_ERROR_TYPE: &str = "Runtime.HandlerTimeout";
+
+/// Tower middleware to stop the handler of a Lambda function before the invocation deadline.
+///
+/// The layer races the handler against the [deadline](Context::deadline) of the invocation
+/// minus a safety margin. When the margin is reached, the handler is cancelled and the
+/// invocation fails with a [Diagnostic] which `error_type` is `Runtime.HandlerTimeout`,
+/// instead of being terminated by Lambda without any structured error.
+///
+/// The layer wraps the handler function, so it must be applied before giving the handler
+/// to the runtime.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{layers::TimeoutLayer, Error, LambdaEvent};
+/// use serde_json::Value;
+/// use std::time::Duration;
+/// use tower::{service_fn, ServiceBuilder};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let func = ServiceBuilder::new()
+///         .layer(TimeoutLayer::new(Duration::from_millis(500)).with_timeout_hook(|context| async move {
+///             println!("invocation {} is about to time out", context.request_id);
+///         }))
+///         .service(service_fn(func));
+///     lambda_runtime::run(func).await
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+pub struct TimeoutLayer<H = fn(Context) -> Ready<()>> {
+    margin: Duration,
+    hook: Option<H>,
+}
+
+impl TimeoutLayer {
+    /// Create a new timeout layer that stops the handler `margin` before the invocation deadline.
+    pub fn new(margin: Duration) -> Self {
+        Self { margin, hook: None }
+    }
+}
+
+impl<H> TimeoutLayer<H> {
+    /// Run a function after the handler is cancelled, and before the error is reported to Lambda.
+    ///
+    /// Use it to flush partial state or to checkpoint the work done so far. The hook receives
+    /// the [Context] of the invocation, and it must complete before the invocation deadline.
+    pub fn with_timeout_hook<G, Fut>(self, hook: G) -> TimeoutLayer<G>
+    where
+        G: Fn(Context) -> Fut + Clone,
+        Fut: Future<Output = ()>,
+    {
+        TimeoutLayer {
+            margin: self.margin,
+            hook: Some(hook),
+        }
+    }
+}
+
+impl<S, H> Layer<S> for TimeoutLayer<H>
+where
+    H: Clone,
+{
+    type Service = TimeoutService<S, H>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimeoutService {
+            inner,
+            margin: self.margin,
+            hook: self.hook.clone(),
+        }
+    }
+}
+
+/// Tower service returned by [TimeoutLayer].
+#[derive(Clone)]
+pub struct TimeoutService<S, H> {
+    inner: S,
+    margin: Duration,
+    hook: Option<H>,
+}
+
+impl<S, H, Fut, Payload> Service<LambdaEvent<Payload>> for TimeoutService<S, H>
+where
+    S: Service<LambdaEvent<Payload>>,
+    S::Error: Into<Diagnostic> + Debug,
+    H: Fn(Context) -> Fut + Clone,
+    Fut: Future<Output = ()>,
+{
+    type Response = S::Response;
+    type Error = Diagnostic;
+    type Future = TimeoutFuture<S::Future, H, Fut>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: LambdaEvent<Payload>) -> Self::Future {
+        let remaining = req
+            .context
+            .deadline()
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .saturating_sub(self.margin);
+        let hook = self.hook.clone().map(|hook| (hook, Box::new(req.context.clone())));
+
+        TimeoutFuture::Running {
+            future: self.inner.call(req),
+            sleep: Box::pin(sleep(remaining)),
+            hook,
+            margin: self.margin,
+        }
+    }
+}
+
+/// Future returned by [TimeoutService].
+#[pin_project(project = TimeoutFutureProj)]
+pub enum TimeoutFuture<F, H, Fut> {
+    /// The handler is running, racing against the deadline.
+    Running {
+        #[pin]
+        future: F,
+        sleep: Pin<Box<Sleep>>,
+        hook: Option<(H, Box<Context>)>,
+        margin: Duration,
+    },
+    /// The handler has been cancelled, and the timeout hook is running.
+    Hook {
+        #[pin]
+        hook: Fut,
+        diagnostic: Option<Diagnostic>,
+    },
+}
+
+impl<F, H, Fut, T, E> Future for TimeoutFuture<F, H, Fut>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<Diagnostic>,
+    H: Fn(Context) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    type Output = Result<T, Diagnostic>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
+        use task::Poll;
+        let (diagnostic, hook) = match self.as_mut().project() {
+            TimeoutFutureProj::Running {
+                future,
+                sleep,
+                hook,
+                margin,
+            } => {
+                if let Poll::Ready(result) = future.poll(cx) {
+                    return Poll::Ready(result.map_err(Into::into));
+                }
+                if sleep.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+
+                error!(margin = ?margin, "handler cancelled before the invocation deadline");
+                let diagnostic = Diagnostic {
+                    error_type: HANDLER_TIMEOUT_ERROR_TYPE.into(),
+                    error_message: format!(
+                        "Handler did not complete {}ms before the invocation deadline",
+                        margin.as_millis()
+                    ),
+                    ..Default::default()
+                };
+                match hook.take() {
+                    Some((hook, context)) => (diagnostic, hook(*context)),
+                    None => return Poll::Ready(Err(diagnostic)),
+                }
+            }
+            TimeoutFutureProj::Hook { hook, diagnostic } => {
+                return hook
+                    .poll(cx)
+                    .map(|_| Err(diagnostic.take().expect("future polled after completion")));
+            }
+        };
+
+        // Drop the handler future to cancel it, and poll the hook until it completes.
+        self.set(TimeoutFuture::Hook {
+            hook,
+            diagnostic: Some(diagnostic),
+        });
+        self.poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+    use tower::{service_fn, ServiceExt};
+
+    fn event_with_deadline(deadline: Duration) -> LambdaEvent<()> {
+        let deadline = SystemTime::now() + deadline;
+        let context = Context {
+            deadline: deadline.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as u64,
+            ..Default::default()
+        };
+        LambdaEvent::new((), context)
+    }
+
+    #[tokio::test]
+    async fn handler_completes_before_the_deadline() {
+        let service = TimeoutLayer::new(Duration::from_millis(100))
+            .layer(service_fn(|_: LambdaEvent<()>| async { Ok::<_, Error>("done") }));
+
+        let response = service.oneshot(event_with_deadline(Duration::from_secs(5))).await;
+        assert_eq!(response.unwrap(), "done");
+    }
+
+    #[tokio::test]
+    async fn handler_cancelled_before_the_deadline() {
+        let hook_called = Arc::new(AtomicBool::new(false));
+        let layer = TimeoutLayer::new(Duration::from_millis(100)).with_timeout_hook({
+            let hook_called = hook_called.clone();
+            move |context: Context| {
+                let hook_called = hook_called.clone();
+                async move {
+                    assert!(context.deadline > 0);
+                    hook_called.store(true, Ordering::SeqCst);
+                }
+            }
+        });
+        let service = layer.layer(service_fn(|_: LambdaEvent<()>| async {
+            sleep(Duration::from_secs(60)).await;
+            Ok::<_, Error>("done")
+        }));
+
+        let diagnostic = service
+            .oneshot(event_with_deadline(Duration::from_millis(200)))
+            .await
+            .unwrap_err();
+        assert_eq!(diagnostic.error_type, "Runtime.HandlerTimeout");
+        assert!(hook_called.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn handler_error() {
+        let service = TimeoutLayer::new(Duration::from_millis(100)).layer(service_fn(|_: LambdaEvent<()>| async {
+            Err::<(), _>(Error::from("something went wrong"))
+        }));
+
+        let diagnostic = service
+            .oneshot(event_with_deadline(Duration::from_secs(5)))
+            .await
+            .unwrap_err();
+        assert_eq!(diagnostic.error_message, "something went wrong");
+    }
+}
@@ -25,6 +25,7 @@ impl<S> Layer<S> for TracingLayer {
 }
 
 /// Tower service returned by [TracingLayer].
+#[derive(Clone)]
 pub struct TracingService<S> {
     inner: S,
 }
@@ -61,11 +62,18 @@ fn request_span(ctx: &Context) -> tracing::Span {
             tracing::info_span!(
                 "Lambda runtime invoke",
                 requestId = &ctx.request_id,
-                xrayTraceId = trace_id
+                xrayTraceId = trace_id,
+                functionArn = &ctx.invoked_function_arn,
+                "faas.coldstart" = ctx.is_cold_start()
             )
         }
         None => {
-            tracing::info_span!("Lambda runtime invoke", requestId = &ctx.request_id)
+            tracing::info_span!(
+                "Lambda runtime invoke",
+                requestId = &ctx.request_id,
+                functionArn = &ctx.invoked_function_arn,
+                "faas.coldstart" = ctx.is_cold_start()
+            )
         }
     }
 }
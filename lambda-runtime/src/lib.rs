@@ -19,29 +19,54 @@ use tokio_stream::Stream;
 use tower::util::ServiceFn;
 pub use tower::{self, service_fn, Service};
 
+#[cfg(feature = "batch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "batch")))]
+pub mod batch;
+pub mod codec;
 /// Diagnostic utilities to convert Rust types into Lambda Error types.
 pub mod diagnostic;
+use codec::{JsonCodec, PayloadCodec};
 pub use diagnostic::Diagnostic;
 
 mod deserializer;
+pub mod extract;
+mod hooks;
+pub use hooks::Hooks;
+
+pub mod idempotency;
 /// Tower middleware to be applied to runtime invocations.
 pub mod layers;
+pub mod metrics;
 mod requests;
+pub mod response_size;
 mod runtime;
 /// Utilities for Lambda Streaming functions.
 pub mod streaming;
+/// Utilities to test Lambda functions end to end against an in-process Runtime API.
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+pub mod testing;
+#[cfg(feature = "batch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "batch")))]
+pub mod time_window;
 
+pub use lambda_runtime_api_client::interceptor;
 /// Utilities to initialize and use `tracing` and `tracing-subscriber` in Lambda Functions.
 #[cfg(feature = "tracing")]
 #[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
 pub use lambda_runtime_api_client::tracing;
+pub use lambda_runtime_api_client::RetryPolicy;
 
 /// Types available to a Lambda function.
 mod types;
+mod xray;
 
 use requests::EventErrorRequest;
-pub use runtime::{LambdaInvocation, Runtime};
-pub use types::{Context, FunctionResponse, IntoFunctionResponse, LambdaEvent, MetadataPrelude, StreamResponse};
+pub use runtime::{LambdaInvocation, Runtime, RuntimeBuilder};
+pub use types::{
+    Borrowed, Context, FunctionResponse, InitializationType, IntoFunctionResponse, LambdaEvent, MetadataPrelude,
+    StreamResponse,
+};
 
 /// Error type that lambdas may result in
 pub type Error = lambda_runtime_api_client::BoxError;
@@ -65,22 +90,38 @@ type RefConfig = Arc<Config>;
 
 impl Config {
     /// Attempts to read configuration from environment variables.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if any of the required environment variables is missing or invalid.
+    /// Use [Config::try_from_env] to handle those errors.
     pub fn from_env() -> Self {
-        Config {
-            function_name: env::var("AWS_LAMBDA_FUNCTION_NAME").expect("Missing AWS_LAMBDA_FUNCTION_NAME env var"),
+        Self::try_from_env().unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Attempts to read configuration from environment variables,
+    /// returning an error if any of the required variables is missing or invalid.
+    pub fn try_from_env() -> Result<Self, Error> {
+        Ok(Config {
+            function_name: env::var("AWS_LAMBDA_FUNCTION_NAME")
+                .map_err(|_| "Missing AWS_LAMBDA_FUNCTION_NAME env var")?,
             memory: env::var("AWS_LAMBDA_FUNCTION_MEMORY_SIZE")
-                .expect("Missing AWS_LAMBDA_FUNCTION_MEMORY_SIZE env var")
+                .map_err(|_| "Missing AWS_LAMBDA_FUNCTION_MEMORY_SIZE env var")?
                 .parse::<i32>()
-                .expect("AWS_LAMBDA_FUNCTION_MEMORY_SIZE env var is not <i32>"),
-            version: env::var("AWS_LAMBDA_FUNCTION_VERSION").expect("Missing AWS_LAMBDA_FUNCTION_VERSION env var"),
+                .map_err(|_| "AWS_LAMBDA_FUNCTION_MEMORY_SIZE env var is not <i32>")?,
+            version: env::var("AWS_LAMBDA_FUNCTION_VERSION")
+                .map_err(|_| "Missing AWS_LAMBDA_FUNCTION_VERSION env var")?,
             log_stream: env::var("AWS_LAMBDA_LOG_STREAM_NAME").unwrap_or_default(),
             log_group: env::var("AWS_LAMBDA_LOG_GROUP_NAME").unwrap_or_default(),
-        }
+        })
     }
 }
 
 /// Return a new [`ServiceFn`] with a closure that takes an event and context as separate arguments.
-#[deprecated(since = "0.5.0", note = "Use `service_fn` and `LambdaEvent` instead")]
+#[deprecated(
+    since = "0.5.0",
+    note = "Use `service_fn` and `LambdaEvent`, or `extract::handler`, instead"
+)]
 pub fn handler_fn<A, F, Fut>(f: F) -> ServiceFn<impl Fn(LambdaEvent<A>) -> Fut>
 where
     F: Fn(A, Context) -> Fut,
@@ -88,6 +129,62 @@ where
     service_fn(move |req: LambdaEvent<A>| f(req.payload, req.context))
 }
 
+/// Declares the entry point of a Lambda function.
+///
+/// The attribute goes on the handler function, and generates a `main` function that
+/// builds a Tokio runtime, initializes the [default tracing subscriber](crate::tracing::init_default_subscriber),
+/// and starts the runtime with the handler, like [run]. The handler is built with [extract::handler],
+/// so it can take a [LambdaEvent] or any other [extractor](extract::FromInvocation) as arguments.
+///
+/// The attribute accepts the following arguments:
+///
+/// - `init = path::to::init`: async function that runs before polling for events, and returns the
+///   state of the handler, which receives it with the [State](extract::State) extractor. If it fails,
+///   the error is reported as an initialization error, like [run_with_init] does.
+/// - `graceful_shutdown` or `graceful_shutdown = path::to::hook`: register a graceful shutdown handler
+///   with [spawn_graceful_shutdown_handler], which runs the async hook when the function shuts down.
+///   It requires the `graceful-shutdown` feature.
+/// - `tracing = false`: don't initialize the default tracing subscriber.
+/// - `flavor = "multi_thread"`: use a multi-threaded Tokio runtime, it requires the `rt-multi-thread`
+///   feature. The default is a current-thread runtime, since the function processes one invocation
+///   at a time. Like [run_local], it runs the handler in a [`LocalSet`](tokio::task::LocalSet),
+///   so the handler future doesn't need to be [`Send`].
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{extract::State, Error, LambdaEvent};
+/// use serde_json::Value;
+/// use std::sync::Arc;
+///
+/// struct Config {
+///     table_name: String,
+/// }
+///
+/// async fn load_config() -> Result<Arc<Config>, Error> {
+///     let table_name = std::env::var("TABLE_NAME")?;
+///     Ok(Arc::new(Config { table_name }))
+/// }
+///
+/// #[lambda_runtime::main(init = load_config)]
+/// async fn handler(event: LambdaEvent<Value>, State(config): State<Arc<Config>>) -> Result<Value, Error> {
+///     Ok(serde_json::json!({ "table": config.table_name, "event": event.payload }))
+/// }
+/// ```
+///
+/// The handler must be an `async fn` whose arguments are extractors:
+/// ```compile_fail
+/// use lambda_runtime::{Error, LambdaEvent};
+/// use serde_json::Value;
+///
+/// #[lambda_runtime::main]
+/// async fn handler(event: LambdaEvent<Value>, table_name: String) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use lambda_runtime_macros::main;
+
 /// Starts the Lambda Rust runtime and begins polling for events on the [Lambda
 /// Runtime APIs](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html).
 ///
@@ -115,9 +212,8 @@ where
     F: Service<LambdaEvent<A>, Response = R>,
     F::Future: Future<Output = Result<R, F::Error>>,
     F::Error: Into<Diagnostic> + fmt::Debug,
-    A: for<'de> Deserialize<'de>,
+    JsonCodec: PayloadCodec<A, B>,
     R: IntoFunctionResponse<B, S>,
-    B: Serialize,
     S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
     D: Into<bytes::Bytes> + Send,
     E: Into<Error> + Send + Debug,
@@ -126,6 +222,161 @@ where
     runtime.run().await
 }
 
+/// Runs the function initialization code and starts the Lambda Rust runtime with the handler
+/// created from its output.
+///
+/// If the initialization fails, the error is reported to the Lambda Runtime API as an
+/// [init error](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html#runtimes-api-initerror),
+/// so the failure shows up with a structured error instead of a crashed process. In that case,
+/// this function returns without polling for events, and your program can exit cleanly.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{Error, service_fn, LambdaEvent};
+/// use serde_json::Value;
+/// use std::sync::Arc;
+///
+/// struct AppState {
+///     table_name: String,
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     lambda_runtime::run_with_init(init(), |state: Arc<AppState>| {
+///         service_fn(move |event: LambdaEvent<Value>| {
+///             let state = state.clone();
+///             async move { func(&state, event).await }
+///         })
+///     })
+///     .await
+/// }
+///
+/// async fn init() -> Result<Arc<AppState>, Error> {
+///     let table_name = std::env::var("TABLE_NAME")?;
+///     Ok(Arc::new(AppState { table_name }))
+/// }
+///
+/// async fn func(state: &AppState, event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(serde_json::json!({ "table": state.table_name, "event": event.payload }))
+/// }
+/// ```
+pub async fn run_with_init<I, T, IE, M, A, F, R, B, S, D, E>(init: I, make_handler: M) -> Result<(), Error>
+where
+    I: Future<Output = Result<T, IE>>,
+    IE: Into<Diagnostic> + fmt::Debug,
+    M: FnOnce(T) -> F,
+    F: Service<LambdaEvent<A>, Response = R>,
+    F::Future: Future<Output = Result<R, F::Error>>,
+    F::Error: Into<Diagnostic> + fmt::Debug,
+    JsonCodec: PayloadCodec<A, B>,
+    R: IntoFunctionResponse<B, S>,
+    S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
+    D: Into<bytes::Bytes> + Send,
+    E: Into<Error> + Send + Debug,
+{
+    match init.await {
+        Ok(state) => run(make_handler(state)).await,
+        Err(err) => {
+            tracing::error!(error = ?err, "function initialization failed");
+            report_init_error(err).await
+        }
+    }
+}
+
+/// Reports an error that happened during the function initialization to the Lambda Runtime API.
+///
+/// Call this function instead of [run] when your setup code fails before the handler can be
+/// created. The endpoint of the Runtime API is read from the `AWS_LAMBDA_RUNTIME_API` environment
+/// variable, use [RuntimeBuilder::report_init_error] to configure it explicitly.
+pub async fn report_init_error(diagnostic: impl Into<Diagnostic>) -> Result<(), Error> {
+    RuntimeBuilder::new().report_init_error(diagnostic).await
+}
+
+/// Starts the Lambda Rust runtime and processes several invocations at the same time on
+/// execution environments that support multi-concurrency, such as Lambda Managed Instances.
+///
+/// The maximum number of concurrent invocations is read from the `AWS_LAMBDA_MAX_CONCURRENCY`
+/// environment variable. When the variable is not set, this function behaves like [run].
+/// Every concurrent invocation is processed with its own clone of the handler, so shared state
+/// must be wrapped in types like [`Arc`].
+///
+/// See [Runtime::run_concurrent] for more details.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{Error, service_fn, LambdaEvent};
+/// use serde_json::Value;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let func = service_fn(func);
+///     lambda_runtime::run_concurrent(func).await?;
+///     Ok(())
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+pub async fn run_concurrent<A, F, R, B, S, D, E>(handler: F) -> Result<(), Error>
+where
+    F: Service<LambdaEvent<A>, Response = R> + Clone,
+    F::Future: Future<Output = Result<R, F::Error>>,
+    F::Error: Into<Diagnostic> + fmt::Debug,
+    JsonCodec: PayloadCodec<A, B>,
+    R: IntoFunctionResponse<B, S>,
+    S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
+    D: Into<bytes::Bytes> + Send,
+    E: Into<Error> + Send + Debug,
+{
+    let runtime = Runtime::new(handler).layer(layers::TracingLayer::new());
+    runtime.run_concurrent().await
+}
+
+/// Starts the Lambda Rust runtime on a current-thread Tokio runtime, without requiring
+/// the handler future to be [`Send`].
+///
+/// This function blocks the current thread, so it must be called from a synchronous `main`
+/// function instead of one annotated with `#[tokio::main]`. Invocations run inside a
+/// [`LocalSet`](tokio::task::LocalSet), so handlers can hold `Rc` and other `!Send` state across
+/// await points, and spawn `!Send` tasks with [`tokio::task::spawn_local`].
+///
+/// It doesn't need the `rt-multi-thread` feature, which can be disabled to reduce the binary size.
+/// See [Runtime::run_local] for more details.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, Error, LambdaEvent};
+/// use serde_json::Value;
+/// use std::{cell::RefCell, rc::Rc};
+///
+/// fn main() -> Result<(), Error> {
+///     let seen = Rc::new(RefCell::new(Vec::new()));
+///     lambda_runtime::run_local(service_fn(move |event: LambdaEvent<Value>| {
+///         let seen = seen.clone();
+///         async move {
+///             seen.borrow_mut().push(event.context.request_id);
+///             tokio::task::yield_now().await;
+///             Ok::<_, Error>(seen.borrow().len())
+///         }
+///     }))
+/// }
+/// ```
+pub fn run_local<A, F, R, B, S, D, E>(handler: F) -> Result<(), Error>
+where
+    F: Service<LambdaEvent<A>, Response = R>,
+    F::Future: Future<Output = Result<R, F::Error>>,
+    F::Error: Into<Diagnostic> + fmt::Debug,
+    JsonCodec: PayloadCodec<A, B>,
+    R: IntoFunctionResponse<B, S>,
+    S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
+    D: Into<bytes::Bytes> + Send,
+    E: Into<Error> + Send + Debug,
+{
+    let runtime = Runtime::new(handler).layer(layers::TracingLayer::new());
+    runtime.run_local()
+}
+
 /// Spawns a task that will be execute a provided async closure when the process
 /// receives unix graceful shutdown signals. If the closure takes longer than 500ms
 /// to execute, an unhandled `SIGKILL` signal might be received.
@@ -234,3 +485,33 @@ where
         });
     });
 }
+
+/// Implementation details of the [main] attribute, not part of the public API.
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    use crate::{report_init_error, Diagnostic, Error};
+    use std::{fmt::Debug, future::Future};
+
+    pub use tokio;
+
+    pub fn init_default_subscriber() {
+        #[cfg(feature = "tracing")]
+        crate::tracing::init_default_subscriber();
+    }
+
+    /// Run the initialization function of a handler, and report its error to the Runtime API.
+    /// Returns `None` when the initialization fails, and the function must exit.
+    pub async fn init<T, E>(init: impl Future<Output = Result<T, E>>) -> Result<Option<T>, Error>
+    where
+        E: Into<Diagnostic> + Debug,
+    {
+        match init.await {
+            Ok(state) => Ok(Some(state)),
+            Err(err) => {
+                tracing::error!(error = ?err, "function initialization failed");
+                report_init_error(err).await.map(|_| None)
+            }
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! Custom metrics published with the [CloudWatch Embedded Metric Format](https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html) (EMF).
+//!
+//! Every invocation gets its own [Metrics] handle, available in [Context::metrics](crate::Context::metrics).
+//! Add the [MetricsLayer](crate::layers::MetricsLayer) to your handler to write the metrics
+//! collected during an invocation as a single EMF log line when the invocation completes.
+//! CloudWatch extracts the metrics from that log line, without calling the CloudWatch APIs.
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::{
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Namespace used when no namespace is configured, same as the one used by the AWS EMF libraries.
+const DEFAULT_NAMESPACE: &str = "aws-embedded-metrics";
+
+/// Unit of a metric value, as defined by the [CloudWatch API](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_MetricDatum.html).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[allow(missing_docs)]
+pub enum Unit {
+    Seconds,
+    Microseconds,
+    Milliseconds,
+    Bytes,
+    Kilobytes,
+    Megabytes,
+    Gigabytes,
+    Terabytes,
+    Bits,
+    Kilobits,
+    Megabits,
+    Gigabits,
+    Terabits,
+    Percent,
+    Count,
+    BytesPerSecond,
+    KilobytesPerSecond,
+    MegabytesPerSecond,
+    GigabytesPerSecond,
+    TerabytesPerSecond,
+    BitsPerSecond,
+    KilobitsPerSecond,
+    MegabitsPerSecond,
+    GigabitsPerSecond,
+    TerabitsPerSecond,
+    CountPerSecond,
+    #[default]
+    None,
+}
+
+impl Unit {
+    /// Name of the unit in the EMF documents.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Unit::Seconds => "Seconds",
+            Unit::Microseconds => "Microseconds",
+            Unit::Milliseconds => "Milliseconds",
+            Unit::Bytes => "Bytes",
+            Unit::Kilobytes => "Kilobytes",
+            Unit::Megabytes => "Megabytes",
+            Unit::Gigabytes => "Gigabytes",
+            Unit::Terabytes => "Terabytes",
+            Unit::Bits => "Bits",
+            Unit::Kilobits => "Kilobits",
+            Unit::Megabits => "Megabits",
+            Unit::Gigabits => "Gigabits",
+            Unit::Terabits => "Terabits",
+            Unit::Percent => "Percent",
+            Unit::Count => "Count",
+            Unit::BytesPerSecond => "Bytes/Second",
+            Unit::KilobytesPerSecond => "Kilobytes/Second",
+            Unit::MegabytesPerSecond => "Megabytes/Second",
+            Unit::GigabytesPerSecond => "Gigabytes/Second",
+            Unit::TerabytesPerSecond => "Terabytes/Second",
+            Unit::BitsPerSecond => "Bits/Second",
+            Unit::KilobitsPerSecond => "Kilobits/Second",
+            Unit::MegabitsPerSecond => "Megabits/Second",
+            Unit::GigabitsPerSecond => "Gigabits/Second",
+            Unit::TerabitsPerSecond => "Terabits/Second",
+            Unit::CountPerSecond => "Count/Second",
+            Unit::None => "None",
+        }
+    }
+}
+
+impl Serialize for Unit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Handle to the metrics collected during an invocation.
+///
+/// The handle is cheap to clone, and all the clones record into the same set of metrics.
+/// Recording the same metric several times in an invocation keeps all its values.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{metrics::Unit, Error, LambdaEvent};
+/// use serde_json::Value;
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     let metrics = &event.context.metrics;
+///     metrics.add_dimension("Operation", "CreateOrder");
+///     metrics.add("OrdersCreated", 1.0, Unit::Count);
+///     metrics.add_high_resolution("CartSize", 3.0, Unit::Count);
+///     metrics.add_property("orderId", "ord-1234");
+///     Ok(event.payload)
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    inner: Arc<Mutex<MetricSet>>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct MetricSet {
+    namespace: Option<String>,
+    dimensions: Vec<(String, String)>,
+    metrics: Vec<Metric>,
+    properties: Map<String, Value>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Metric {
+    name: String,
+    unit: Unit,
+    high_resolution: bool,
+    values: Vec<f64>,
+}
+
+impl Metrics {
+    /// Set the CloudWatch namespace of the metrics.
+    pub fn set_namespace(&self, namespace: impl Into<String>) {
+        self.lock().namespace = Some(namespace.into());
+    }
+
+    /// Add a dimension to all the metrics of the invocation.
+    /// Adding a dimension that already exists replaces its value.
+    pub fn add_dimension(&self, name: impl Into<String>, value: impl Into<String>) {
+        let (name, value) = (name.into(), value.into());
+        let mut set = self.lock();
+        match set.dimensions.iter_mut().find(|(key, _)| *key == name) {
+            Some((_, current)) => *current = value,
+            None => set.dimensions.push((name, value)),
+        }
+    }
+
+    /// Record a value for a metric with standard resolution, aggregated by CloudWatch in one minute periods.
+    pub fn add(&self, name: impl Into<String>, value: f64, unit: Unit) {
+        self.record(name.into(), value, unit, false);
+    }
+
+    /// Record a value for a metric with high resolution, aggregated by CloudWatch in one second periods.
+    pub fn add_high_resolution(&self, name: impl Into<String>, value: f64, unit: Unit) {
+        self.record(name.into(), value, unit, true);
+    }
+
+    /// Add a property to the EMF document. Properties are not metrics, but they can be
+    /// searched with CloudWatch Logs Insights, like the id of the resource that the invocation processed.
+    pub fn add_property(&self, key: impl Into<String>, value: impl Into<Value>) {
+        self.lock().properties.insert(key.into(), value.into());
+    }
+
+    /// Whether any metric has been recorded since the last flush.
+    pub fn is_empty(&self) -> bool {
+        self.lock().metrics.is_empty()
+    }
+
+    /// Serialize the recorded metrics as an EMF document, and clear them.
+    ///
+    /// The namespace and the dimensions are kept, so they apply to the metrics recorded after
+    /// the flush. Returns `None` when no metric has been recorded.
+    pub fn flush(&self) -> Option<String> {
+        let mut set = self.lock();
+        if set.metrics.is_empty() {
+            return None;
+        }
+        let metrics = std::mem::take(&mut set.metrics);
+        let properties = std::mem::take(&mut set.properties);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Some(emf_document(&set, metrics, properties, timestamp).to_string())
+    }
+
+    /// Lock the metric set. A panic while recording a metric doesn't leave the set
+    /// in an inconsistent state, so a poisoned lock is still used.
+    fn lock(&self) -> MutexGuard<'_, MetricSet> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn record(&self, name: String, value: f64, unit: Unit, high_resolution: bool) {
+        let mut set = self.lock();
+        match set.metrics.iter_mut().find(|metric| metric.name == name) {
+            Some(metric) => metric.values.push(value),
+            None => set.metrics.push(Metric {
+                name,
+                unit,
+                high_resolution,
+                values: vec![value],
+            }),
+        }
+    }
+}
+
+impl PartialEq for Metrics {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner) || *self.lock() == *other.lock()
+    }
+}
+
+impl Eq for Metrics {}
+
+/// Build the EMF document for a set of metrics. The dimensions, properties and metric values
+/// are top level members of the document, and the `_aws` member describes which ones are metrics.
+fn emf_document(set: &MetricSet, metrics: Vec<Metric>, properties: Map<String, Value>, timestamp: u64) -> Value {
+    let definitions: Vec<Value> = metrics
+        .iter()
+        .map(|metric| {
+            let mut definition = serde_json::json!({ "Name": metric.name, "Unit": metric.unit });
+            if metric.high_resolution {
+                definition["StorageResolution"] = 1.into();
+            }
+            definition
+        })
+        .collect();
+    let dimension_names: Vec<&str> = set.dimensions.iter().map(|(name, _)| name.as_str()).collect();
+
+    let mut document = properties;
+    for (name, value) in &set.dimensions {
+        document.insert(name.clone(), value.clone().into());
+    }
+    for metric in metrics {
+        let value = match metric.values.as_slice() {
+            [value] => Value::from(*value),
+            values => Value::from(values.to_vec()),
+        };
+        document.insert(metric.name, value);
+    }
+    document.insert(
+        "_aws".into(),
+        serde_json::json!({
+            "Timestamp": timestamp,
+            "CloudWatchMetrics": [{
+                "Namespace": set.namespace.as_deref().unwrap_or(DEFAULT_NAMESPACE),
+                "Dimensions": [dimension_names],
+                "Metrics": definitions,
+            }],
+        }),
+    );
+    Value::Object(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn emf_document_format() {
+        let metrics = Metrics::default();
+        metrics.set_namespace("Orders");
+        metrics.add_dimension("Service", "checkout");
+        metrics.add_dimension("Service", "orders");
+        metrics.add("Created", 1.0, Unit::Count);
+        metrics.add("Created", 2.0, Unit::Count);
+        metrics.add_high_resolution("Latency", 12.5, Unit::Milliseconds);
+        metrics.add_property("orderId", "ord-1234");
+
+        let mut document: Value = serde_json::from_str(&metrics.flush().unwrap()).unwrap();
+        assert!(document["_aws"]["Timestamp"].as_u64().unwrap() > 0);
+        document["_aws"]["Timestamp"] = 0.into();
+        let expected = json!({
+            "_aws": {
+                "Timestamp": 0,
+                "CloudWatchMetrics": [{
+                    "Namespace": "Orders",
+                    "Dimensions": [["Service"]],
+                    "Metrics": [
+                        {"Name": "Created", "Unit": "Count"},
+                        {"Name": "Latency", "Unit": "Milliseconds", "StorageResolution": 1},
+                    ],
+                }],
+            },
+            "Service": "orders",
+            "Created": [1.0, 2.0],
+            "Latency": 12.5,
+            "orderId": "ord-1234",
+        });
+        assert_eq!(expected, document);
+    }
+
+    #[test]
+    fn flush_clears_the_metrics() {
+        let metrics = Metrics::default();
+        assert!(metrics.flush().is_none());
+
+        metrics.add_dimension("Service", "orders");
+        metrics.add("Created", 1.0, Unit::Count);
+        let clone = metrics.clone();
+        assert!(!clone.is_empty());
+        assert!(clone.flush().unwrap().contains(DEFAULT_NAMESPACE));
+        assert!(metrics.is_empty());
+        assert!(metrics.flush().is_none());
+
+        metrics.add("Deleted", 1.0, Unit::Count);
+        let document: Value = serde_json::from_str(&metrics.flush().unwrap()).unwrap();
+        assert_eq!(document["Service"], "orders");
+        assert!(document.get("Created").is_none());
+    }
+}
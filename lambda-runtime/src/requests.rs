@@ -1,9 +1,15 @@
-use crate::{types::ToStreamErrorTrailer, Diagnostic, Error, FunctionResponse, IntoFunctionResponse};
+use crate::{
+    types::ToStreamErrorTrailer,
+    xray::{XRayErrorCause, XRAY_ERROR_CAUSE_HEADER},
+    Diagnostic, Error, FunctionResponse,
+};
 use bytes::Bytes;
-use http::{header::CONTENT_TYPE, Method, Request, Uri};
+use http::{
+    header::{CONNECTION, CONTENT_TYPE},
+    Method, Request, Uri,
+};
 use lambda_runtime_api_client::{body::Body, build_request};
-use serde::Serialize;
-use std::{fmt::Debug, marker::PhantomData, str::FromStr};
+use std::{fmt::Debug, str::FromStr};
 use tokio_stream::{Stream, StreamExt};
 
 pub(crate) trait IntoRequest {
@@ -25,54 +31,30 @@ impl IntoRequest for NextEventRequest {
 }
 
 // /runtime/invocation/{AwsRequestId}/response
-pub(crate) struct EventCompletionRequest<'a, R, B, S, D, E>
-where
-    R: IntoFunctionResponse<B, S>,
-    B: Serialize,
-    S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
-    D: Into<Bytes> + Send,
-    E: Into<Error> + Send + Debug,
-{
+pub(crate) struct EventCompletionRequest<'a, S> {
     pub(crate) request_id: &'a str,
-    pub(crate) body: R,
-    pub(crate) _unused_b: PhantomData<B>,
-    pub(crate) _unused_s: PhantomData<S>,
+    pub(crate) body: FunctionResponse<Bytes, S>,
 }
 
-impl<'a, R, B, D, E, S> EventCompletionRequest<'a, R, B, S, D, E>
-where
-    R: IntoFunctionResponse<B, S>,
-    B: Serialize,
-    S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
-    D: Into<Bytes> + Send,
-    E: Into<Error> + Send + Debug,
-{
-    /// Initialize a new EventCompletionRequest
-    pub(crate) fn new(request_id: &'a str, body: R) -> EventCompletionRequest<'a, R, B, S, D, E> {
-        EventCompletionRequest {
-            request_id,
-            body,
-            _unused_b: PhantomData::<B>,
-            _unused_s: PhantomData::<S>,
-        }
+impl<'a, S> EventCompletionRequest<'a, S> {
+    /// Initialize a new EventCompletionRequest with a response that has already been encoded
+    pub(crate) fn new(request_id: &'a str, body: FunctionResponse<Bytes, S>) -> EventCompletionRequest<'a, S> {
+        EventCompletionRequest { request_id, body }
     }
 }
 
-impl<R, B, S, D, E> IntoRequest for EventCompletionRequest<'_, R, B, S, D, E>
+impl<S, D, E> IntoRequest for EventCompletionRequest<'_, S>
 where
-    R: IntoFunctionResponse<B, S>,
-    B: Serialize,
     S: Stream<Item = Result<D, E>> + Unpin + Send + 'static,
     D: Into<Bytes> + Send,
     E: Into<Error> + Send + Debug,
 {
     fn into_req(self) -> Result<Request<Body>, Error> {
-        match self.body.into_response() {
+        match self.body {
             FunctionResponse::BufferedResponse(body) => {
                 let uri = format!("/2018-06-01/runtime/invocation/{}/response", self.request_id);
                 let uri = Uri::from_str(&uri)?;
 
-                let body = serde_json::to_vec(&body)?;
                 let body = Body::from(body);
 
                 let req = build_request().method(Method::POST).uri(uri).body(body)?;
@@ -130,7 +112,7 @@ where
 
 #[test]
 fn test_event_completion_request() {
-    let req = EventCompletionRequest::new("id", "hello, world!");
+    let req = EventCompletionRequest::<Body>::new("id", FunctionResponse::BufferedResponse("hello, world!".into()));
     let req = req.into_req().unwrap();
     let expected = Uri::from_static("/2018-06-01/runtime/invocation/id/response");
     assert_eq!(req.method(), Method::POST);
@@ -145,6 +127,7 @@ fn test_event_completion_request() {
 pub(crate) struct EventErrorRequest<'a> {
     pub(crate) request_id: &'a str,
     pub(crate) diagnostic: Diagnostic,
+    pub(crate) xray_error_cause: bool,
 }
 
 impl<'a> EventErrorRequest<'a> {
@@ -152,6 +135,15 @@ impl<'a> EventErrorRequest<'a> {
         EventErrorRequest {
             request_id,
             diagnostic: diagnostic.into(),
+            xray_error_cause: false,
+        }
+    }
+
+    /// Send the diagnostic as an X-Ray error cause too.
+    pub(crate) fn with_xray_error_cause(self, xray_error_cause: bool) -> Self {
+        Self {
+            xray_error_cause,
+            ..self
         }
     }
 }
@@ -160,6 +152,39 @@ impl IntoRequest for EventErrorRequest<'_> {
     fn into_req(self) -> Result<Request<Body>, Error> {
         let uri = format!("/2018-06-01/runtime/invocation/{}/error", self.request_id);
         let uri = Uri::from_str(&uri)?;
+
+        let mut req = build_request()
+            .method(Method::POST)
+            .uri(uri)
+            .header("lambda-runtime-function-error-type", "unhandled");
+        if self.xray_error_cause {
+            if let Some(cause) = XRayErrorCause::new(&self.diagnostic).header_value() {
+                req = req.header(XRAY_ERROR_CAUSE_HEADER, cause);
+            }
+        }
+
+        let body = serde_json::to_vec(&self.diagnostic)?;
+        let req = req.body(Body::from(body))?;
+        Ok(req)
+    }
+}
+
+// /runtime/init/error
+pub(crate) struct InitErrorRequest {
+    pub(crate) diagnostic: Diagnostic,
+}
+
+impl InitErrorRequest {
+    pub(crate) fn new(diagnostic: impl Into<Diagnostic>) -> InitErrorRequest {
+        InitErrorRequest {
+            diagnostic: diagnostic.into(),
+        }
+    }
+}
+
+impl IntoRequest for InitErrorRequest {
+    fn into_req(self) -> Result<Request<Body>, Error> {
+        let uri = Uri::from_static("/2018-06-01/runtime/init/error");
         let body = serde_json::to_vec(&self.diagnostic)?;
         let body = Body::from(body);
 
@@ -172,6 +197,51 @@ impl IntoRequest for EventErrorRequest<'_> {
     }
 }
 
+// /runtime/restore/next
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct RestoreNextRequest;
+
+impl IntoRequest for RestoreNextRequest {
+    fn into_req(self) -> Result<Request<Body>, Error> {
+        // The request blocks until the execution environment is restored from the snapshot.
+        // Don't keep its connection in the pool, because it doesn't survive the restore.
+        let req = build_request()
+            .method(Method::GET)
+            .uri(Uri::from_static("/2018-06-01/runtime/restore/next"))
+            .header(CONNECTION, "close")
+            .body(Default::default())?;
+        Ok(req)
+    }
+}
+
+// /runtime/restore/error
+pub(crate) struct RestoreErrorRequest {
+    pub(crate) diagnostic: Diagnostic,
+}
+
+impl RestoreErrorRequest {
+    pub(crate) fn new(diagnostic: impl Into<Diagnostic>) -> RestoreErrorRequest {
+        RestoreErrorRequest {
+            diagnostic: diagnostic.into(),
+        }
+    }
+}
+
+impl IntoRequest for RestoreErrorRequest {
+    fn into_req(self) -> Result<Request<Body>, Error> {
+        let uri = Uri::from_static("/2018-06-01/runtime/restore/error");
+        let body = serde_json::to_vec(&self.diagnostic)?;
+        let body = Body::from(body);
+
+        let req = build_request()
+            .method(Method::POST)
+            .uri(uri)
+            .header("lambda-runtime-function-error-type", self.diagnostic.error_type)
+            .body(body)?;
+        Ok(req)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -195,7 +265,9 @@ mod tests {
             diagnostic: Diagnostic {
                 error_type: "InvalidEventDataError".into(),
                 error_message: "Error parsing event data".into(),
+                ..Default::default()
             },
+            xray_error_cause: false,
         };
         let req = req.into_req().unwrap();
         let expected = Uri::from_static("/2018-06-01/runtime/invocation/id/error");
@@ -206,4 +278,61 @@ mod tests {
             None => false,
         });
     }
+
+    #[test]
+    fn test_event_error_request_with_xray_error_cause() {
+        let diagnostic = Diagnostic {
+            error_type: "InvalidEventDataError".into(),
+            error_message: "Error parsing event data".into(),
+            ..Default::default()
+        };
+
+        let req = EventErrorRequest::new("id", diagnostic.clone()).into_req().unwrap();
+        assert!(!req.headers().contains_key(XRAY_ERROR_CAUSE_HEADER));
+
+        let req = EventErrorRequest::new("id", diagnostic)
+            .with_xray_error_cause(true)
+            .into_req()
+            .unwrap();
+        let cause: serde_json::Value =
+            serde_json::from_slice(req.headers()[XRAY_ERROR_CAUSE_HEADER].as_bytes()).unwrap();
+        assert_eq!(cause["exceptions"][0]["type"], "InvalidEventDataError");
+        assert_eq!(cause["exceptions"][0]["message"], "Error parsing event data");
+    }
+
+    #[test]
+    fn test_init_error_request() {
+        let req = InitErrorRequest::new(Diagnostic {
+            error_type: "InitError".into(),
+            error_message: "Unable to load configuration".into(),
+            ..Default::default()
+        });
+        let req = req.into_req().unwrap();
+        let expected = Uri::from_static("/2018-06-01/runtime/init/error");
+        assert_eq!(req.method(), Method::POST);
+        assert_eq!(req.uri(), &expected);
+        assert_eq!(req.headers()["lambda-runtime-function-error-type"], "unhandled");
+        assert!(match req.headers().get("User-Agent") {
+            Some(header) => header.to_str().unwrap().starts_with("aws-lambda-rust/"),
+            None => false,
+        });
+    }
+
+    #[test]
+    fn test_restore_requests() {
+        let req = RestoreNextRequest.into_req().unwrap();
+        assert_eq!(req.method(), Method::GET);
+        assert_eq!(req.uri(), &Uri::from_static("/2018-06-01/runtime/restore/next"));
+        assert_eq!(req.headers()[CONNECTION], "close");
+
+        let req = RestoreErrorRequest::new(Diagnostic {
+            error_type: "RestoreError".into(),
+            error_message: "Unable to reconnect".into(),
+            ..Default::default()
+        });
+        let req = req.into_req().unwrap();
+        assert_eq!(req.method(), Method::POST);
+        assert_eq!(req.uri(), &Uri::from_static("/2018-06-01/runtime/restore/error"));
+        assert_eq!(req.headers()["lambda-runtime-function-error-type"], "RestoreError");
+    }
 }
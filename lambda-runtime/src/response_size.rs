@@ -0,0 +1,300 @@
+//! Guard against buffered responses that exceed the payload size limit of Lambda.
+//!
+//! Lambda rejects buffered responses larger than 6 MB with an opaque error, after the handler
+//! has done all its work. The runtime checks the size of every encoded response before sending
+//! it, and applies the strategy of its [ResponseSizeGuard] to the responses that are too large:
+//!
+//! - by default, the invocation fails with a [Diagnostic] which `error_type` is
+//!   `Function.ResponseSizeTooLarge`, and which message includes the size of the response.
+//! - [ResponseSizeGuard::with_streaming] sends the response as a
+//!   [streaming response](https://docs.aws.amazon.com/lambda/latest/dg/configuration-response-streaming.html)
+//!   instead, which supports larger payloads. It only works when the function is invoked with
+//!   response streaming.
+//! - [ResponseSizeGuard::with_offload] calls a function that stores the response somewhere else,
+//!   like an S3 bucket, and returns a smaller payload that points to it.
+//!
+//! # Example
+//! ```no_run
+//! use bytes::Bytes;
+//! use lambda_runtime::{response_size::ResponseSizeGuard, service_fn, Error, LambdaEvent, RuntimeBuilder};
+//! use serde_json::{json, Value};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let guard = ResponseSizeGuard::new().with_offload(|response| async move {
+//!         let key = format!("reports/{}.json", response.request_id);
+//!         // Upload `response.body` to `key` with your storage client.
+//!         let pointer = serde_json::to_vec(&json!({ "location": key }))?;
+//!         Ok::<_, Error>(Bytes::from(pointer))
+//!     });
+//!     let runtime = RuntimeBuilder::new()
+//!         .with_response_size_guard(guard)
+//!         .build(service_fn(func))?;
+//!     runtime.run().await
+//! }
+//!
+//! async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//! ```
+use crate::{
+    requests::{EventCompletionRequest, IntoRequest},
+    Diagnostic, FunctionResponse, StreamResponse,
+};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use lambda_runtime_api_client::{body::Body, BoxError};
+use std::{future::Future, sync::Arc};
+use tracing::{error, warn};
+
+/// Maximum size of a buffered response accepted by Lambda, 6 MB.
+pub const MAX_RESPONSE_SIZE: usize = 6 * 1024 * 1024;
+
+/// Error type reported to Lambda when a response exceeds the size limit.
+const RESPONSE_SIZE_ERROR_TYPE: &str = "Function.ResponseSizeTooLarge";
+
+type OffloadFn = Arc<dyn Fn(OversizedResponse) -> BoxFuture<'static, Result<Bytes, Diagnostic>> + Send + Sync>;
+
+/// Buffered response that exceeds the size limit, given to the function registered
+/// with [ResponseSizeGuard::with_offload].
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub struct OversizedResponse {
+    /// The request id of the invocation.
+    pub request_id: String,
+    /// The encoded response of the handler.
+    pub body: Bytes,
+}
+
+/// What the runtime does with the responses that exceed the size limit.
+#[derive(Clone)]
+enum OverflowStrategy {
+    Fail,
+    Stream,
+    Offload(OffloadFn),
+}
+
+/// Size limit of the buffered responses, and what to do with the responses that exceed it.
+/// See the [module documentation](self) for the available strategies.
+///
+/// The runtime uses [ResponseSizeGuard::new] by default, set a different guard with
+/// [RuntimeBuilder::with_response_size_guard](crate::RuntimeBuilder::with_response_size_guard).
+/// Streaming responses returned by the handler are not checked.
+#[derive(Clone)]
+pub struct ResponseSizeGuard {
+    limit: usize,
+    strategy: OverflowStrategy,
+}
+
+impl Default for ResponseSizeGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResponseSizeGuard {
+    /// Create a guard that fails the invocations which responses exceed [MAX_RESPONSE_SIZE].
+    pub fn new() -> Self {
+        Self {
+            limit: MAX_RESPONSE_SIZE,
+            strategy: OverflowStrategy::Fail,
+        }
+    }
+
+    /// Set the size limit of the buffered responses, in bytes. Defaults to [MAX_RESPONSE_SIZE].
+    pub fn with_limit(self, limit: usize) -> Self {
+        Self { limit, ..self }
+    }
+
+    /// Send the responses that exceed the limit as streaming responses.
+    ///
+    /// The function must be invoked with response streaming, through a function URL
+    /// configured with the `RESPONSE_STREAM` invoke mode, or with the `InvokeWithResponseStream` API.
+    pub fn with_streaming(self) -> Self {
+        Self {
+            strategy: OverflowStrategy::Stream,
+            ..self
+        }
+    }
+
+    /// Call a function with the responses that exceed the limit, and send the payload it
+    /// returns instead. The function typically uploads the response to external storage,
+    /// and returns a pointer to it.
+    ///
+    /// When the function fails, or when its payload also exceeds the limit, the invocation fails.
+    pub fn with_offload<F, Fut, B, E>(self, offload: F) -> Self
+    where
+        F: Fn(OversizedResponse) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<B, E>> + Send + 'static,
+        B: Into<Bytes>,
+        E: Into<Diagnostic>,
+    {
+        let offload: OffloadFn = Arc::new(move |response| {
+            let future = offload(response);
+            Box::pin(async move { future.await.map(Into::into).map_err(Into::into) })
+        });
+        Self {
+            strategy: OverflowStrategy::Offload(offload),
+            ..self
+        }
+    }
+
+    /// The size limit of the buffered responses, in bytes.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Build the request that sends an encoded buffered response to the Lambda Runtime API,
+    /// or the diagnostic of the invocation when the response can't be sent.
+    pub(crate) fn guard(&self, request_id: &str, body: Bytes) -> GuardedRequest {
+        if body.len() <= self.limit {
+            return GuardedRequest::Ready(GuardOutcome::Complete(completion_request(request_id, body)));
+        }
+
+        let size = body.len();
+        match &self.strategy {
+            OverflowStrategy::Fail => GuardedRequest::Ready(GuardOutcome::Fail(size_error(size, self.limit))),
+            OverflowStrategy::Stream => {
+                warn!(
+                    size,
+                    limit = self.limit,
+                    "response exceeds the size limit, sending it as a streaming response"
+                );
+                let response = StreamResponse::from(tokio_stream::once(Ok::<_, BoxError>(body)));
+                GuardedRequest::Ready(GuardOutcome::Complete(
+                    EventCompletionRequest::new(request_id, FunctionResponse::StreamingResponse(response)).into_req(),
+                ))
+            }
+            OverflowStrategy::Offload(offload) => {
+                warn!(
+                    size,
+                    limit = self.limit,
+                    "response exceeds the size limit, offloading it"
+                );
+                let limit = self.limit;
+                let request_id = request_id.to_owned();
+                let pointer = offload(OversizedResponse {
+                    request_id: request_id.clone(),
+                    body,
+                });
+                GuardedRequest::Offload(Box::pin(async move {
+                    match pointer.await {
+                        Ok(pointer) if pointer.len() <= limit => {
+                            GuardOutcome::Complete(completion_request(&request_id, pointer))
+                        }
+                        Ok(pointer) => GuardOutcome::Fail(size_error(pointer.len(), limit)),
+                        Err(diagnostic) => {
+                            error!(error = ?diagnostic, "failed to offload the response");
+                            GuardOutcome::Fail(diagnostic)
+                        }
+                    }
+                }))
+            }
+        }
+    }
+}
+
+/// Outcome of [ResponseSizeGuard::guard] for a response.
+// The ready outcome is moved out right away, boxing it would only add an allocation.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum GuardOutcome {
+    /// The request that sends the response.
+    Complete(Result<http::Request<Body>, BoxError>),
+    /// The response can't be sent, and the invocation fails with this diagnostic.
+    Fail(Diagnostic),
+}
+
+/// Outcome built by [ResponseSizeGuard::guard].
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum GuardedRequest {
+    /// The outcome is known right away.
+    Ready(GuardOutcome),
+    /// The response is being offloaded, the outcome is known once the future completes.
+    Offload(BoxFuture<'static, GuardOutcome>),
+}
+
+fn completion_request(request_id: &str, body: Bytes) -> Result<http::Request<Body>, BoxError> {
+    EventCompletionRequest::<Body>::new(request_id, FunctionResponse::BufferedResponse(body)).into_req()
+}
+
+fn size_error(size: usize, limit: usize) -> Diagnostic {
+    error!(size, limit, "response exceeds the size limit");
+    Diagnostic::new(
+        RESPONSE_SIZE_ERROR_TYPE,
+        format!("Response size of {size} bytes exceeds the limit of {limit} bytes"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    async fn send(guard: &ResponseSizeGuard, body: &'static str) -> Result<http::Request<Body>, Diagnostic> {
+        let outcome = match guard.guard("id", Bytes::from_static(body.as_bytes())) {
+            GuardedRequest::Ready(outcome) => outcome,
+            GuardedRequest::Offload(future) => future.await,
+        };
+        match outcome {
+            GuardOutcome::Complete(request) => Ok(request.unwrap()),
+            GuardOutcome::Fail(diagnostic) => Err(diagnostic),
+        }
+    }
+
+    async fn body(request: http::Request<Body>) -> Bytes {
+        request.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    #[tokio::test]
+    async fn responses_within_the_limit() {
+        let request = send(&ResponseSizeGuard::new().with_limit(5), "hello").await.unwrap();
+        assert_eq!(request.uri(), "/2018-06-01/runtime/invocation/id/response");
+        assert_eq!(body(request).await, "hello");
+    }
+
+    #[tokio::test]
+    async fn fail_oversized_responses() {
+        let diagnostic = send(&ResponseSizeGuard::new().with_limit(4), "hello")
+            .await
+            .unwrap_err();
+        assert_eq!(diagnostic.error_type, RESPONSE_SIZE_ERROR_TYPE);
+        assert_eq!(
+            diagnostic.error_message,
+            "Response size of 5 bytes exceeds the limit of 4 bytes"
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_oversized_responses() {
+        let request = send(&ResponseSizeGuard::new().with_limit(4).with_streaming(), "hello")
+            .await
+            .unwrap();
+        assert_eq!(request.uri(), "/2018-06-01/runtime/invocation/id/response");
+        assert_eq!(request.headers()["Lambda-Runtime-Function-Response-Mode"], "streaming");
+        assert!(body(request).await.ends_with(b"hello"));
+    }
+
+    #[tokio::test]
+    async fn offload_oversized_responses() {
+        let guard = ResponseSizeGuard::new()
+            .with_limit(4)
+            .with_offload(|response| async move {
+                assert_eq!(response.request_id, "id");
+                match &response.body[..] {
+                    b"hello" => Ok("s3"),
+                    b"large" => Ok("too large"),
+                    _ => Err(Error::from("upload failed")),
+                }
+            });
+
+        let request = send(&guard, "hello").await.unwrap();
+        assert_eq!(request.uri(), "/2018-06-01/runtime/invocation/id/response");
+        assert_eq!(body(request).await, "s3");
+
+        let diagnostic = send(&guard, "large").await.unwrap_err();
+        assert_eq!(diagnostic.error_type, RESPONSE_SIZE_ERROR_TYPE);
+
+        let diagnostic = send(&guard, "other").await.unwrap_err();
+        assert_eq!(diagnostic.error_message, "upload failed");
+    }
+}
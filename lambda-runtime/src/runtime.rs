@@ -1,12 +1,14 @@
 use crate::{
+    codec::{JsonCodec, PayloadCodec},
+    hooks::{InvocationHooks, LifecycleHooks},
     layers::{CatchPanicService, RuntimeApiClientService, RuntimeApiResponseService},
-    requests::{IntoRequest, NextEventRequest},
-    types::{invoke_request_id, IntoFunctionResponse, LambdaEvent},
-    Config, Context, Diagnostic,
+    requests::{InitErrorRequest, IntoRequest, NextEventRequest},
+    response_size::ResponseSizeGuard,
+    types::{invoke_request_id, IntoFunctionResponse, LambdaEvent, Sandbox},
+    Config, Context, Diagnostic, Hooks, InitializationType,
 };
 use http_body_util::BodyExt;
-use lambda_runtime_api_client::{BoxError, Client as ApiClient};
-use serde::{Deserialize, Serialize};
+use lambda_runtime_api_client::{interceptor::Interceptor, BoxError, Client as ApiClient, RetryPolicy};
 use std::{env, fmt::Debug, future::Future, sync::Arc};
 use tokio_stream::{Stream, StreamExt};
 use tower::{Layer, Service, ServiceExt};
@@ -26,6 +28,10 @@ pub struct LambdaInvocation {
 
 /* ------------------------------------------ RUNTIME ------------------------------------------ */
 
+/// Environment variable with the maximum number of concurrent invocations that
+/// an execution environment can receive, set by Lambda Managed Instances.
+const MAX_CONCURRENCY_ENV_VAR: &str = "AWS_LAMBDA_MAX_CONCURRENCY";
+
 /// Lambda runtime executing a handler function on incoming requests.
 ///
 /// Middleware can be added to a runtime using the [Runtime::layer] method in order to execute
@@ -53,6 +59,9 @@ pub struct Runtime<S> {
     service: S,
     config: Arc<Config>,
     client: Arc<ApiClient>,
+    concurrency: usize,
+    hooks: LifecycleHooks,
+    sandbox: Arc<Sandbox>,
 }
 
 impl<F, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>
@@ -73,9 +82,8 @@ where
     F: Service<LambdaEvent<EventPayload>, Response = Response>,
     F::Future: Future<Output = Result<Response, F::Error>>,
     F::Error: Into<Diagnostic> + Debug,
-    EventPayload: for<'de> Deserialize<'de>,
+    JsonCodec: PayloadCodec<EventPayload, BufferedResponse>,
     Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
-    BufferedResponse: Serialize,
     StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
     StreamItem: Into<bytes::Bytes> + Send,
     StreamError: Into<BoxError> + Send + Debug,
@@ -89,15 +97,301 @@ where
     /// Note that manually creating a [Runtime] does not add tracing to the executed handler
     /// as is done by [super::run]. If you want to add the default tracing functionality, call
     /// [Runtime::layer] with a [super::layers::TracingLayer].
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the configuration or the Runtime API endpoint cannot be read from
+    /// the environment. Use a [RuntimeBuilder] to provide them explicitly or to handle those errors.
     pub fn new(handler: F) -> Self {
         trace!("Loading config from env");
         let config = Arc::new(Config::from_env());
         let client = Arc::new(ApiClient::builder().build().expect("Unable to create a runtime client"));
         Self {
-            service: wrap_handler(handler, client.clone()),
+            service: wrap_handler(
+                handler,
+                client.clone(),
+                JsonCodec,
+                false,
+                Default::default(),
+                Default::default(),
+            ),
             config,
             client,
+            concurrency: 1,
+            hooks: Default::default(),
+            sandbox: Arc::new(Sandbox::from_env()),
+        }
+        .with_concurrency(max_concurrency_from_env())
+    }
+}
+
+/// Builder to create a [Runtime] with explicit dependencies instead of reading them from
+/// the environment.
+///
+/// Any dependency that is not configured explicitly is resolved from the environment
+/// variables that Lambda sets, like [Runtime::new] does. Unlike [Runtime::new],
+/// [RuntimeBuilder::build] returns an error instead of panicking when they are missing.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{Config, Error, LambdaEvent, RuntimeBuilder};
+/// use serde_json::Value;
+/// use tower::service_fn;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let config = Config {
+///         function_name: "my-function".to_string(),
+///         memory: 128,
+///         version: "$LATEST".to_string(),
+///         ..Default::default()
+///     };
+///
+///     let runtime = RuntimeBuilder::new()
+///         .with_config(config)
+///         .with_endpoint("http://127.0.0.1:9001".parse()?)
+///         .build(service_fn(func))?;
+///     runtime.run().await?;
+///     Ok(())
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+#[derive(Default)]
+pub struct RuntimeBuilder<C = JsonCodec> {
+    client: Option<ApiClient>,
+    config: Option<Config>,
+    endpoint: Option<http::Uri>,
+    retry_policy: Option<RetryPolicy>,
+    interceptors: Vec<Box<dyn Interceptor>>,
+    concurrency: Option<usize>,
+    xray_error_cause: bool,
+    snap_start: Option<bool>,
+    hooks: Hooks,
+    response_size_guard: ResponseSizeGuard,
+    codec: C,
+}
+
+impl RuntimeBuilder {
+    /// Create a new builder without any explicit dependency.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<C> RuntimeBuilder<C> {
+    /// Use a prebuilt client to interact with the Lambda Runtime API.
+    ///
+    /// The client takes precedence over any endpoint set with [RuntimeBuilder::with_endpoint].
+    pub fn with_client(self, client: ApiClient) -> Self {
+        Self {
+            client: Some(client),
+            ..self
+        }
+    }
+
+    /// Use a custom function configuration instead of reading it from the environment.
+    pub fn with_config(self, config: Config) -> Self {
+        Self {
+            config: Some(config),
+            ..self
+        }
+    }
+
+    /// Use a custom Lambda Runtime API endpoint instead of reading it from the
+    /// `AWS_LAMBDA_RUNTIME_API` environment variable.
+    pub fn with_endpoint(self, endpoint: http::Uri) -> Self {
+        Self {
+            endpoint: Some(endpoint),
+            ..self
+        }
+    }
+
+    /// Retry the requests to the Lambda Runtime API that fail because of transient errors,
+    /// like connections refused by a restarting local emulator, instead of stopping the runtime.
+    ///
+    /// The policy is ignored when a client is set with [RuntimeBuilder::with_client], configure it
+    /// on the client instead. Requests are not retried by default.
+    pub fn with_retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Add an interceptor called for every request sent to the Lambda Runtime API, to measure
+    /// how long the runtime waited for invocations and how long it took to send the responses.
+    ///
+    /// Interceptors are ignored when a client is set with [RuntimeBuilder::with_client], add them
+    /// to the client instead. See the [interceptor](crate::interceptor) module for more details.
+    pub fn with_interceptor(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.interceptors.push(Box::new(interceptor));
+        self
+    }
+
+    /// Set the maximum number of invocations processed at the same time by [Runtime::run_concurrent].
+    /// See [Runtime::with_concurrency] for more details.
+    pub fn with_concurrency(self, concurrency: usize) -> Self {
+        Self {
+            concurrency: Some(concurrency),
+            ..self
+        }
+    }
+
+    /// Send the `Lambda-Runtime-Function-XRay-Error-Cause` header when an invocation fails,
+    /// so that the error shows up with its fault details in the X-Ray trace of the invocation.
+    ///
+    /// The error cause is built from the [Diagnostic] returned by the handler, including its
+    /// [stack trace](Diagnostic::stack_trace) and its [causes](Diagnostic::causes). It's only sent
+    /// for invocations that have an X-Ray trace id. Disabled by default.
+    pub fn with_xray_error_cause(self, xray_error_cause: bool) -> Self {
+        Self {
+            xray_error_cause,
+            ..self
+        }
+    }
+
+    /// Run the runtime in [SnapStart](https://docs.aws.amazon.com/lambda/latest/dg/snapstart.html) mode.
+    ///
+    /// In SnapStart mode, the runtime runs the [before_checkpoint](Hooks::before_checkpoint) hooks
+    /// after initializing, and waits on the `/runtime/restore/next` endpoint while Lambda takes the
+    /// snapshot of the execution environment. Once the environment is restored, the runtime reloads
+    /// the [Config] from the environment variables, and runs the [after_restore](Hooks::after_restore)
+    /// hooks before polling for the first event.
+    ///
+    /// By default, SnapStart mode is enabled when the `AWS_LAMBDA_INITIALIZATION_TYPE` environment
+    /// variable is `snap-start`.
+    pub fn with_snap_start(self, snap_start: bool) -> Self {
+        Self {
+            snap_start: Some(snap_start),
+            ..self
+        }
+    }
+
+    /// Run the given [Hooks] at the different stages of the lifecycle of the function.
+    pub fn with_hooks(self, hooks: Hooks) -> Self {
+        Self { hooks, ..self }
+    }
+
+    /// Check the size of the buffered responses of the handler with the given guard, to handle
+    /// the responses that exceed the payload size limit of Lambda before sending them.
+    ///
+    /// By default, the invocations which responses exceed 6 MB fail with a `Function.ResponseSizeTooLarge`
+    /// error. See the [response_size](crate::response_size) module for the other strategies.
+    pub fn with_response_size_guard(self, response_size_guard: ResponseSizeGuard) -> Self {
+        Self {
+            response_size_guard,
+            ..self
+        }
+    }
+
+    /// Use a different codec to decode the event payloads and to encode the buffered responses
+    /// of the handler. The runtime uses [JsonCodec] by default.
+    ///
+    /// See the [codec](crate::codec) module for the available codecs. Handlers built with
+    /// [extract::handler](crate::extract::handler) decode the payload with their extractors,
+    /// so they only run with [JsonCodec].
+    pub fn with_codec<D>(self, codec: D) -> RuntimeBuilder<D> {
+        RuntimeBuilder {
+            client: self.client,
+            config: self.config,
+            endpoint: self.endpoint,
+            retry_policy: self.retry_policy,
+            interceptors: self.interceptors,
+            concurrency: self.concurrency,
+            xray_error_cause: self.xray_error_cause,
+            snap_start: self.snap_start,
+            hooks: self.hooks,
+            response_size_guard: self.response_size_guard,
+            codec,
+        }
+    }
+
+    /// Create a new runtime that executes the provided handler for incoming requests.
+    #[allow(clippy::type_complexity)]
+    pub fn build<'a, F, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>(
+        self,
+        handler: F,
+    ) -> Result<
+        Runtime<
+            RuntimeApiClientService<
+                RuntimeApiResponseService<
+                    CatchPanicService<'a, F>,
+                    EventPayload,
+                    Response,
+                    BufferedResponse,
+                    StreamingResponse,
+                    StreamItem,
+                    StreamError,
+                    C,
+                >,
+            >,
+        >,
+        BoxError,
+    >
+    where
+        F: Service<LambdaEvent<EventPayload>, Response = Response>,
+        F::Future: Future<Output = Result<Response, F::Error>>,
+        F::Error: Into<Diagnostic> + Debug,
+        C: PayloadCodec<EventPayload, BufferedResponse> + Clone,
+        Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
+        StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
+        StreamItem: Into<bytes::Bytes> + Send,
+        StreamError: Into<BoxError> + Send + Debug,
+    {
+        let config = match self.config {
+            Some(config) => config,
+            None => {
+                trace!("Loading config from env");
+                Config::try_from_env()?
+            }
+        };
+        let concurrency = self.concurrency.unwrap_or_else(max_concurrency_from_env);
+        let client = build_client(self.client, self.endpoint, self.retry_policy, self.interceptors)?;
+        let initialization_type = match (self.snap_start, InitializationType::from_env()) {
+            (Some(true), _) => InitializationType::SnapStart,
+            (Some(false), InitializationType::SnapStart) => InitializationType::OnDemand,
+            (_, initialization_type) => initialization_type,
+        };
+
+        let client = Arc::new(client);
+        let runtime = Runtime {
+            service: wrap_handler(
+                handler,
+                client.clone(),
+                self.codec,
+                self.xray_error_cause,
+                Arc::new(self.hooks.invocation),
+                Arc::new(self.response_size_guard),
+            ),
+            config: Arc::new(config),
+            client,
+            concurrency: 1,
+            hooks: self.hooks.lifecycle,
+            sandbox: Arc::new(Sandbox::new(initialization_type)),
+        };
+        Ok(runtime.with_concurrency(concurrency))
+    }
+
+    /// Report an error that happened during the function initialization to the
+    /// [`/runtime/init/error`](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html#runtimes-api-initerror)
+    /// endpoint of the Lambda Runtime API.
+    ///
+    /// Only the client, endpoint, retry policy, and interceptor settings of the builder are used.
+    /// After reporting the error, the function should exit without polling for events. See
+    /// [crate::run_with_init] for a helper that runs the initialization code and reports its errors.
+    ///
+    /// Returns an error when the Runtime API rejects the report.
+    pub async fn report_init_error(self, diagnostic: impl Into<Diagnostic>) -> Result<(), BoxError> {
+        let client = build_client(self.client, self.endpoint, self.retry_policy, self.interceptors)?;
+        let req = InitErrorRequest::new(diagnostic).into_req()?;
+        let res = client.call(req).await?;
+        if !res.status().is_success() {
+            return Err(format!("unexpected status reporting the initialization error: {}", res.status()).into());
         }
+        Ok(())
     }
 }
 
@@ -133,6 +427,22 @@ impl<S> Runtime<S> {
             client: self.client,
             config: self.config,
             service: layer.layer(self.service),
+            concurrency: self.concurrency,
+            hooks: self.hooks,
+            sandbox: self.sandbox,
+        }
+    }
+
+    /// Set the maximum number of invocations that [Runtime::run_concurrent] processes at the same time.
+    ///
+    /// By default, this value is read from the `AWS_LAMBDA_MAX_CONCURRENCY` environment variable,
+    /// which is set for multi-concurrency execution environments such as Lambda Managed Instances.
+    /// When the variable is not present, the runtime processes one invocation at a time.
+    /// A value of `0` is treated as `1`.
+    pub fn with_concurrency(self, concurrency: usize) -> Self {
+        Self {
+            concurrency: concurrency.max(1),
+            ..self
         }
     }
 }
@@ -142,17 +452,65 @@ where
     S: Service<LambdaInvocation, Response = (), Error = BoxError>,
 {
     /// Start the runtime and begin polling for events on the Lambda Runtime API.
-    pub async fn run(self) -> Result<(), BoxError> {
+    pub async fn run(mut self) -> Result<(), BoxError> {
+        self.start().await?;
         let incoming = incoming(&self.client);
-        Self::run_with_incoming(self.service, self.config, incoming).await
+        Self::run_with_incoming(self.service, self.config, self.sandbox, incoming).await
+    }
+
+    /// Start the runtime on a new current-thread Tokio runtime, and block the current thread
+    /// while it polls for events.
+    ///
+    /// The runtime runs inside a [`LocalSet`](tokio::task::LocalSet), so the handler future
+    /// doesn't need to be [`Send`], and it can spawn `!Send` tasks with [`tokio::task::spawn_local`].
+    /// Streaming responses must still be `Send`, but the [channel](crate::streaming::channel)
+    /// body can be fed from a local task.
+    ///
+    /// # Panics
+    ///
+    /// This method panics when it's called from within a Tokio runtime.
+    pub fn run_local(self) -> Result<(), BoxError> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        tokio::task::LocalSet::new().block_on(&runtime, self.run())
+    }
+
+    /// Run the lifecycle hooks that precede the first invocation. In SnapStart mode, this waits
+    /// until the execution environment is restored, and reloads the state cached by the runtime.
+    async fn start(&mut self) -> Result<(), BoxError> {
+        let snap_start = self.sandbox.is_snap_start();
+        std::mem::take(&mut self.hooks).start(&self.client, snap_start).await?;
+
+        if snap_start {
+            // Restored environments have their own log stream, and their lifecycle starts with the restore.
+            if let Ok(config) = Config::try_from_env() {
+                self.config = Arc::new(config);
+            }
+            self.sandbox = Arc::new(Sandbox::new(InitializationType::SnapStart));
+        }
+        Ok(())
     }
 
     /// Internal utility function to start the runtime with a customized incoming stream.
     /// This implements the core of the [Runtime::run] method.
     pub(crate) async fn run_with_incoming(
+        service: S,
+        config: Arc<Config>,
+        sandbox: Arc<Sandbox>,
+        incoming: impl Stream<Item = Result<http::Response<hyper::body::Incoming>, BoxError>> + Send,
+    ) -> Result<(), BoxError> {
+        Self::process_incoming(service, config, sandbox, incoming, true).await
+    }
+
+    /// Process the invocations of an incoming stream one at a time.
+    ///
+    /// When `set_amzn_trace_env` is `true`, the `_X_AMZN_TRACE_ID` environment variable is updated
+    /// with the trace id of every invocation. This is only safe when a single invocation is in flight.
+    async fn process_incoming(
         mut service: S,
         config: Arc<Config>,
+        sandbox: Arc<Sandbox>,
         incoming: impl Stream<Item = Result<http::Response<hyper::body::Incoming>, BoxError>> + Send,
+        set_amzn_trace_env: bool,
     ) -> Result<(), BoxError> {
         tokio::pin!(incoming);
         while let Some(next_event_response) = incoming.next().await {
@@ -171,11 +529,14 @@ where
             // Build the invocation such that it can be sent to the service right away
             // when it is ready
             let body = incoming.collect().await?.to_bytes();
-            let context = Context::new(invoke_request_id(&parts.headers)?, config.clone(), &parts.headers)?;
+            let mut context = Context::new(invoke_request_id(&parts.headers)?, config.clone(), &parts.headers)?;
+            sandbox.start_invocation(&mut context);
             let invocation = LambdaInvocation { parts, body, context };
 
             // Setup Amazon's default tracing data
-            amzn_trace_env(&invocation.context);
+            if set_amzn_trace_env {
+                amzn_trace_env(&invocation.context);
+            }
 
             // Wait for service to be ready
             let ready = service.ready().await?;
@@ -187,12 +548,83 @@ where
     }
 }
 
+impl<S> Runtime<S>
+where
+    S: Service<LambdaInvocation, Response = (), Error = BoxError> + Clone,
+{
+    /// Start the runtime and process up to [Runtime::with_concurrency] invocations at the same time.
+    ///
+    /// The runtime keeps one `/next` long-poll request in flight for every invocation that it can
+    /// process, and drives each invocation with its own clone of the service. All invocations run
+    /// concurrently on the task that awaits this future, so CPU-bound handlers should offload their
+    /// work with `tokio::task::spawn_blocking` or `tokio::spawn`.
+    ///
+    /// Unlike [Runtime::run], this method never sets the `_X_AMZN_TRACE_ID` environment variable,
+    /// because its value would be shared by all the invocations in flight. Read the trace id from
+    /// [Context::xray_trace_id] instead.
+    ///
+    /// When the concurrency is `1`, this method behaves exactly like [Runtime::run].
+    pub async fn run_concurrent(mut self) -> Result<(), BoxError> {
+        if self.concurrency <= 1 {
+            return self.run().await;
+        }
+
+        trace!(concurrency = self.concurrency, "Starting concurrent runtime");
+        self.start().await?;
+        let workers = (0..self.concurrency).map(|_| {
+            let incoming = incoming(&self.client);
+            Self::process_incoming(
+                self.service.clone(),
+                self.config.clone(),
+                self.sandbox.clone(),
+                incoming,
+                false,
+            )
+        });
+        futures::future::try_join_all(workers).await?;
+        Ok(())
+    }
+}
+
 /* ------------------------------------------- UTILS ------------------------------------------- */
 
+fn build_client(
+    client: Option<ApiClient>,
+    endpoint: Option<http::Uri>,
+    retry_policy: Option<RetryPolicy>,
+    interceptors: Vec<Box<dyn Interceptor>>,
+) -> Result<ApiClient, BoxError> {
+    if let Some(client) = client {
+        return Ok(client);
+    }
+    let mut builder = ApiClient::builder();
+    if let Some(endpoint) = endpoint {
+        builder = builder.with_endpoint(endpoint);
+    }
+    if let Some(retry_policy) = retry_policy {
+        builder = builder.with_retry_policy(retry_policy);
+    }
+    for interceptor in interceptors {
+        builder = builder.with_interceptor(interceptor);
+    }
+    Ok(builder.build()?)
+}
+
+fn max_concurrency_from_env() -> usize {
+    env::var(MAX_CONCURRENCY_ENV_VAR)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(1)
+}
+
 #[allow(clippy::type_complexity)]
-fn wrap_handler<'a, F, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>(
+fn wrap_handler<'a, F, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError, C>(
     handler: F,
     client: Arc<ApiClient>,
+    codec: C,
+    xray_error_cause: bool,
+    hooks: Arc<InvocationHooks>,
+    response_size_guard: Arc<ResponseSizeGuard>,
 ) -> RuntimeApiClientService<
     RuntimeApiResponseService<
         CatchPanicService<'a, F>,
@@ -202,21 +634,24 @@ fn wrap_handler<'a, F, EventPayload, Response, BufferedResponse, StreamingRespon
         StreamingResponse,
         StreamItem,
         StreamError,
+        C,
     >,
 >
 where
     F: Service<LambdaEvent<EventPayload>, Response = Response>,
     F::Future: Future<Output = Result<Response, F::Error>>,
     F::Error: Into<Diagnostic> + Debug,
-    EventPayload: for<'de> Deserialize<'de>,
+    C: PayloadCodec<EventPayload, BufferedResponse> + Clone,
     Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
-    BufferedResponse: Serialize,
     StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
     StreamItem: Into<bytes::Bytes> + Send,
     StreamError: Into<BoxError> + Send + Debug,
 {
     let safe_service = CatchPanicService::new(handler);
-    let response_service = RuntimeApiResponseService::new(safe_service);
+    let response_service = RuntimeApiResponseService::with_codec(safe_service, codec)
+        .with_xray_error_cause(xray_error_cause)
+        .with_hooks(hooks)
+        .with_response_size_guard(response_size_guard);
     RuntimeApiClientService::new(response_service, client)
 }
 
@@ -248,15 +683,17 @@ fn amzn_trace_env(ctx: &Context) {
 mod endpoint_tests {
     use super::{incoming, wrap_handler};
     use crate::{
+        codec::{JsonCodec, PayloadCodec},
         requests::{EventCompletionRequest, EventErrorRequest, IntoRequest, NextEventRequest},
-        Config, Diagnostic, Error, Runtime,
+        types::Sandbox,
+        Config, Diagnostic, Error, FunctionResponse, Runtime, RuntimeBuilder,
     };
     use futures::future::BoxFuture;
     use http::{HeaderValue, StatusCode};
     use http_body_util::BodyExt;
     use httpmock::prelude::*;
 
-    use lambda_runtime_api_client::Client;
+    use lambda_runtime_api_client::{body::Body, Client};
     use std::{env, sync::Arc};
     use tokio_stream::StreamExt;
 
@@ -311,7 +748,11 @@ mod endpoint_tests {
         let base = server.base_url().parse().expect("Invalid mock server Uri");
         let client = Client::builder().with_endpoint(base).build()?;
 
-        let req = EventCompletionRequest::new("156cb537-e2d4-11e8-9b34-d36013741fb9", "{}");
+        let body = PayloadCodec::<(), _>::encode(&JsonCodec, "{}")?;
+        let req = EventCompletionRequest::<Body>::new(
+            "156cb537-e2d4-11e8-9b34-d36013741fb9",
+            FunctionResponse::BufferedResponse(body),
+        );
         let req = req.into_req()?;
 
         let rsp = client.call(req).await?;
@@ -326,6 +767,7 @@ mod endpoint_tests {
         let diagnostic = Diagnostic {
             error_type: "InvalidEventDataError".into(),
             error_message: "Error parsing event data".into(),
+            ..Default::default()
         };
         let body = serde_json::to_string(&diagnostic)?;
 
@@ -344,6 +786,7 @@ mod endpoint_tests {
         let req = EventErrorRequest {
             request_id: "156cb537-e2d4-11e8-9b34-d36013741fb9",
             diagnostic,
+            xray_error_cause: false,
         };
         let req = req.into_req()?;
         let rsp = client.call(req).await?;
@@ -353,6 +796,26 @@ mod endpoint_tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_rejected_init_error() -> Result<(), Error> {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/2018-06-01/runtime/init/error");
+            then.status(403).body("");
+        });
+
+        let base = server.base_url().parse().expect("Invalid mock server Uri");
+        let err = RuntimeBuilder::new()
+            .with_endpoint(base)
+            .report_init_error("unable to connect to the database")
+            .await
+            .expect_err("the rejected report must fail");
+
+        mock.assert_async().await;
+        assert!(err.to_string().contains("403"));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn successful_end_to_end_run() -> Result<(), Error> {
         let server = MockServer::start();
@@ -408,11 +871,60 @@ mod endpoint_tests {
         let runtime = Runtime {
             client: client.clone(),
             config: Arc::new(config),
-            service: wrap_handler(f, client),
+            service: wrap_handler(f, client, JsonCodec, false, Default::default(), Default::default()),
+            concurrency: 1,
+            hooks: Default::default(),
+            sandbox: Arc::new(Sandbox::from_env()),
         };
         let client = &runtime.client;
         let incoming = incoming(client).take(1);
-        Runtime::run_with_incoming(runtime.service, runtime.config, incoming).await?;
+        Runtime::run_with_incoming(runtime.service, runtime.config, runtime.sandbox, incoming).await?;
+
+        next_request.assert_async().await;
+        next_response.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn builder_end_to_end_run() -> Result<(), Error> {
+        let server = MockServer::start();
+        let request_id = "156cb537-e2d4-11e8-9b34-d36013741fb9";
+        let deadline = "1542409706888";
+
+        let next_request = server.mock(|when, then| {
+            when.method(GET).path("/2018-06-01/runtime/invocation/next");
+            then.status(200)
+                .header("content-type", "application/json")
+                .header("lambda-runtime-aws-request-id", request_id)
+                .header("lambda-runtime-deadline-ms", deadline)
+                .body("{}");
+        });
+        let next_response = server.mock(|when, then| {
+            when.method(POST)
+                .path(format!("/2018-06-01/runtime/invocation/{request_id}/response"))
+                .body("\"builder_fn\"");
+            then.status(200).body("");
+        });
+
+        async fn func(event: crate::LambdaEvent<serde_json::Value>) -> Result<String, Error> {
+            Ok(event.context.env_config.function_name.clone())
+        }
+
+        let config = Config {
+            function_name: "builder_fn".to_string(),
+            memory: 128,
+            version: "1".to_string(),
+            log_stream: "test_stream".to_string(),
+            log_group: "test_log".to_string(),
+        };
+        let runtime = RuntimeBuilder::new()
+            .with_config(config)
+            .with_endpoint(server.base_url().parse()?)
+            .with_concurrency(1)
+            .build(crate::service_fn(func))?;
+
+        let incoming = incoming(&runtime.client).take(1);
+        Runtime::run_with_incoming(runtime.service, runtime.config, runtime.sandbox, incoming).await?;
 
         next_request.assert_async().await;
         next_response.assert_async().await;
@@ -462,11 +974,14 @@ mod endpoint_tests {
         let runtime = Runtime {
             client: client.clone(),
             config,
-            service: wrap_handler(f, client),
+            service: wrap_handler(f, client, JsonCodec, false, Default::default(), Default::default()),
+            concurrency: 1,
+            hooks: Default::default(),
+            sandbox: Arc::new(Sandbox::from_env()),
         };
         let client = &runtime.client;
         let incoming = incoming(client).take(1);
-        Runtime::run_with_incoming(runtime.service, runtime.config, incoming).await?;
+        Runtime::run_with_incoming(runtime.service, runtime.config, runtime.sandbox, incoming).await?;
 
         next_request.assert_async().await;
         next_response.assert_async().await;
@@ -0,0 +1,1067 @@
+//! In-process implementation of the [Lambda Runtime API](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html)
+//! to test functions end to end without deploying them.
+//!
+//! [MockRuntimeApi] starts a local HTTP server that serves the `/runtime/invocation/next`,
+//! `/runtime/invocation/{AwsRequestId}/response`, `/runtime/invocation/{AwsRequestId}/error`,
+//! `/runtime/init/error`, `/runtime/restore/next` and `/runtime/restore/error` endpoints. Tests queue invocations on the server and assert on
+//! what the [Runtime] posted back, exercising the same code paths that run in Lambda.
+//!
+//! The server listens on a local TCP port, or on an in-memory transport with
+//! [MockRuntimeApi::start_in_memory], for environments where tests can't bind ports.
+//!
+//! # Example
+//! ```
+//! use lambda_runtime::{
+//!     service_fn,
+//!     testing::{MockInvocation, MockRuntimeApi},
+//!     Error, LambdaEvent,
+//! };
+//! use serde_json::{json, Value};
+//!
+//! async fn echo(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Error> {
+//! let api = MockRuntimeApi::start().await?;
+//! let runtime = api.runtime(service_fn(echo))?;
+//! tokio::spawn(runtime.run());
+//!
+//! let outcome = api.invoke(MockInvocation::json(&json!({ "hello": "world" }))?).await?;
+//! let response: Value = outcome.into_response()?;
+//! assert_eq!(response, json!({ "hello": "world" }));
+//! # Ok(())
+//! # }
+//! ```
+use crate::{
+    codec::{JsonCodec, PayloadCodec},
+    layers::{CatchPanicService, RuntimeApiClientService, RuntimeApiResponseService},
+    types::{ClientContext, CognitoIdentity},
+    Config, Diagnostic, Error, IntoFunctionResponse, LambdaEvent, Runtime, RuntimeBuilder,
+};
+use bytes::Bytes;
+use http::{header::HeaderName, HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri};
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Incoming, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use lambda_runtime_api_client::{
+    connect::{self, DuplexConnector},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt::Debug,
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+use tokio_stream::Stream;
+use tower::Service;
+
+const NEXT_EVENT_PATH: &str = "/2018-06-01/runtime/invocation/next";
+const INIT_ERROR_PATH: &str = "/2018-06-01/runtime/init/error";
+const RESTORE_NEXT_PATH: &str = "/2018-06-01/runtime/restore/next";
+const RESTORE_ERROR_PATH: &str = "/2018-06-01/runtime/restore/error";
+const INVOCATION_PATH_PREFIX: &str = "/2018-06-01/runtime/invocation/";
+
+const DEFAULT_FUNCTION_ARN: &str = "arn:aws:lambda:us-east-1:123456789012:function:test_fn";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+static REQUEST_ID_SEQUENCE: AtomicU64 = AtomicU64::new(1);
+
+/// An invocation queued on a [MockRuntimeApi] and delivered to the
+/// function through the `/runtime/invocation/next` endpoint.
+#[derive(Clone, Debug)]
+pub struct MockInvocation {
+    request_id: String,
+    body: Bytes,
+    headers: HeaderMap,
+}
+
+impl MockInvocation {
+    /// Create a new invocation with a raw payload.
+    ///
+    /// The invocation gets a unique request id, a deadline three seconds in the future
+    /// and a test function ARN. Use the `with_*` methods to override them.
+    pub fn new(body: impl Into<Bytes>) -> Self {
+        let sequence = REQUEST_ID_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        let invocation = MockInvocation {
+            request_id: format!("00000000-0000-0000-0000-{sequence:012}"),
+            body: body.into(),
+            headers: HeaderMap::new(),
+        };
+        invocation
+            .with_deadline(SystemTime::now() + DEFAULT_TIMEOUT)
+            .with_header("lambda-runtime-invoked-function-arn", DEFAULT_FUNCTION_ARN)
+    }
+
+    /// Create a new invocation with a payload serialized as JSON.
+    pub fn json<T: Serialize>(payload: &T) -> Result<Self, Error> {
+        Ok(Self::new(serde_json::to_vec(payload)?))
+    }
+
+    /// The request id that the function receives in `Context::request_id`.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Override the generated request id.
+    pub fn with_request_id(self, request_id: impl Into<String>) -> Self {
+        Self {
+            request_id: request_id.into(),
+            ..self
+        }
+    }
+
+    /// Set the execution deadline of the invocation.
+    pub fn with_deadline(self, deadline: SystemTime) -> Self {
+        let millis = deadline
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        self.with_header("lambda-runtime-deadline-ms", millis.to_string())
+    }
+
+    /// Set the ARN of the function being invoked.
+    pub fn with_invoked_function_arn(self, arn: impl AsRef<str>) -> Self {
+        self.with_header("lambda-runtime-invoked-function-arn", arn)
+    }
+
+    /// Set the X-Ray trace id of the invocation.
+    pub fn with_xray_trace_id(self, trace_id: impl AsRef<str>) -> Self {
+        self.with_header("lambda-runtime-trace-id", trace_id)
+    }
+
+    /// Set the client context sent by the AWS Mobile SDK.
+    pub fn with_client_context(self, client_context: &ClientContext) -> Result<Self, Error> {
+        Ok(self.with_header("lambda-runtime-client-context", serde_json::to_string(client_context)?))
+    }
+
+    /// Set the Cognito identity of the caller.
+    pub fn with_identity(self, identity: &CognitoIdentity) -> Result<Self, Error> {
+        Ok(self.with_header("lambda-runtime-cognito-identity", serde_json::to_string(identity)?))
+    }
+
+    /// Set an arbitrary header on the `/runtime/invocation/next` response.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the name or the value are not valid header values.
+    pub fn with_header(mut self, name: &'static str, value: impl AsRef<str>) -> Self {
+        let value = HeaderValue::from_str(value.as_ref()).expect("invalid header value");
+        self.headers.insert(HeaderName::from_static(name), value);
+        self
+    }
+}
+
+/// A request that the runtime sent to the [MockRuntimeApi].
+#[derive(Clone, Debug)]
+pub struct PostedRequest {
+    /// The request headers. Trailers sent at the end of
+    /// streaming responses are appended to these headers.
+    pub headers: HeaderMap,
+    /// The request body.
+    pub body: Bytes,
+}
+
+impl PostedRequest {
+    /// Deserialize the request body from JSON.
+    pub fn json<T: for<'de> Deserialize<'de>>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+/// The outcome of a [MockInvocation], as reported by the runtime.
+#[derive(Clone, Debug)]
+pub enum InvocationOutcome {
+    /// The runtime posted to `/runtime/invocation/{AwsRequestId}/response`.
+    Response(PostedRequest),
+    /// The runtime posted to `/runtime/invocation/{AwsRequestId}/error`.
+    Error(PostedRequest),
+}
+
+impl InvocationOutcome {
+    /// Whether the runtime reported an error for the invocation.
+    pub fn is_error(&self) -> bool {
+        matches!(self, InvocationOutcome::Error(_))
+    }
+
+    /// Deserialize the function response from JSON.
+    /// Returns an error if the runtime reported an error instead.
+    pub fn into_response<T: for<'de> Deserialize<'de>>(self) -> Result<T, Error> {
+        match self {
+            InvocationOutcome::Response(request) => request.json(),
+            InvocationOutcome::Error(request) => Err(format!(
+                "expected a function response, got an error: {}",
+                String::from_utf8_lossy(&request.body)
+            )
+            .into()),
+        }
+    }
+
+    /// Deserialize the [Diagnostic] reported by the runtime.
+    /// Returns an error if the runtime reported a successful response instead.
+    pub fn into_diagnostic(self) -> Result<Diagnostic, Error> {
+        match self {
+            InvocationOutcome::Error(request) => request.json(),
+            InvocationOutcome::Response(_) => Err("expected an error, got a function response".into()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Outcomes {
+    pending: HashMap<String, oneshot::Sender<InvocationOutcome>>,
+    init_errors: Vec<PostedRequest>,
+    restore_errors: Vec<PostedRequest>,
+}
+
+struct State {
+    sender: mpsc::UnboundedSender<MockInvocation>,
+    receiver: tokio::sync::Mutex<mpsc::UnboundedReceiver<MockInvocation>>,
+    outcomes: Mutex<Outcomes>,
+}
+
+/// Transport the [MockRuntimeApi] listens on.
+enum Transport {
+    Tcp(SocketAddr),
+    InMemory(DuplexConnector),
+}
+
+/// Local HTTP server implementing the Lambda Runtime API.
+///
+/// The server is bound to a random port on the loopback interface, or to an in-memory
+/// transport, and it's shut down when this value is dropped.
+pub struct MockRuntimeApi {
+    transport: Transport,
+    state: Arc<State>,
+    server: JoinHandle<()>,
+}
+
+impl MockRuntimeApi {
+    /// Start a new server on a random local port.
+    pub async fn start() -> Result<Self, Error> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+
+        let state = State::new();
+        let server_state = state.clone();
+        let server = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(server_state.clone(), stream));
+            }
+        });
+
+        Ok(MockRuntimeApi {
+            transport: Transport::Tcp(addr),
+            state,
+            server,
+        })
+    }
+
+    /// Start a new server on an in-memory transport, without binding any port.
+    ///
+    /// The server is only reachable with the clients created by [MockRuntimeApi::client].
+    pub fn start_in_memory() -> Self {
+        let (connector, mut listener) = connect::duplex();
+
+        let state = State::new();
+        let server_state = state.clone();
+        let server = tokio::spawn(async move {
+            while let Some(stream) = listener.accept().await {
+                tokio::spawn(serve(server_state.clone(), stream));
+            }
+        });
+
+        MockRuntimeApi {
+            transport: Transport::InMemory(connector),
+            state,
+            server,
+        }
+    }
+
+    /// The base URI of the server, equivalent to the value of `AWS_LAMBDA_RUNTIME_API`.
+    ///
+    /// Servers started with [MockRuntimeApi::start_in_memory] have a placeholder endpoint
+    /// that doesn't resolve to them.
+    pub fn endpoint(&self) -> Uri {
+        match &self.transport {
+            Transport::Tcp(addr) => format!("http://{addr}").parse().expect("invalid socket address"),
+            Transport::InMemory(_) => Uri::from_static("http://localhost"),
+        }
+    }
+
+    /// Create a Runtime API client connected to this server.
+    pub fn client(&self) -> Result<Client, Error> {
+        let builder = Client::builder().with_endpoint(self.endpoint());
+        let builder = match &self.transport {
+            Transport::Tcp(_) => builder,
+            Transport::InMemory(connector) => builder.with_connector(connector.clone()),
+        };
+        Ok(builder.build()?)
+    }
+
+    /// Create a [Runtime] that polls this server for invocations instead of the Lambda Runtime API.
+    ///
+    /// The runtime uses a test [Config] that doesn't depend on the process environment,
+    /// and it processes one invocation at a time unless [Runtime::with_concurrency] is called.
+    /// Use [MockRuntimeApi::client] with a [RuntimeBuilder] for further customization.
+    #[allow(clippy::type_complexity)]
+    pub fn runtime<'a, F, EventPayload, Response, BufferedResponse, StreamingResponse, StreamItem, StreamError>(
+        &self,
+        handler: F,
+    ) -> Result<
+        Runtime<
+            RuntimeApiClientService<
+                RuntimeApiResponseService<
+                    CatchPanicService<'a, F>,
+                    EventPayload,
+                    Response,
+                    BufferedResponse,
+                    StreamingResponse,
+                    StreamItem,
+                    StreamError,
+                >,
+            >,
+        >,
+        Error,
+    >
+    where
+        F: Service<LambdaEvent<EventPayload>, Response = Response>,
+        F::Future: Future<Output = Result<Response, F::Error>>,
+        F::Error: Into<Diagnostic> + Debug,
+        JsonCodec: PayloadCodec<EventPayload, BufferedResponse>,
+        Response: IntoFunctionResponse<BufferedResponse, StreamingResponse>,
+        StreamingResponse: Stream<Item = Result<StreamItem, StreamError>> + Unpin + Send + 'static,
+        StreamItem: Into<Bytes> + Send,
+        StreamError: Into<Error> + Send + Debug,
+    {
+        RuntimeBuilder::new()
+            .with_client(self.client()?)
+            .with_config(test_config())
+            .with_concurrency(1)
+            .build(handler)
+    }
+
+    /// Queue an invocation and wait until the runtime reports its outcome.
+    ///
+    /// This method waits forever if no runtime is polling the server,
+    /// wrap it in [tokio::time::timeout] to bound the wait.
+    pub async fn invoke(&self, invocation: MockInvocation) -> Result<InvocationOutcome, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.state
+            .outcomes
+            .lock()
+            .expect("mock Runtime API state poisoned")
+            .pending
+            .insert(invocation.request_id.clone(), tx);
+
+        self.state
+            .sender
+            .send(invocation)
+            .map_err(|_| "mock Runtime API is not running")?;
+        Ok(rx
+            .await
+            .map_err(|_| "mock Runtime API shut down before the invocation completed")?)
+    }
+
+    /// The requests that the runtime sent to `/runtime/init/error`.
+    pub fn init_errors(&self) -> Vec<PostedRequest> {
+        self.state
+            .outcomes
+            .lock()
+            .expect("mock Runtime API state poisoned")
+            .init_errors
+            .clone()
+    }
+
+    /// The requests that the runtime sent to `/runtime/restore/error`.
+    ///
+    /// The server restores the execution environment as soon as the runtime
+    /// calls `/runtime/restore/next`, without taking any snapshot.
+    pub fn restore_errors(&self) -> Vec<PostedRequest> {
+        self.state
+            .outcomes
+            .lock()
+            .expect("mock Runtime API state poisoned")
+            .restore_errors
+            .clone()
+    }
+}
+
+impl Drop for MockRuntimeApi {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+impl State {
+    fn new() -> Arc<Self> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Arc::new(State {
+            sender,
+            receiver: tokio::sync::Mutex::new(receiver),
+            outcomes: Mutex::new(Outcomes::default()),
+        })
+    }
+}
+
+async fn serve<S>(state: Arc<State>, stream: S)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = service_fn(move |req| handle(state.clone(), req));
+    if let Err(error) = http1::Builder::new()
+        .serve_connection(TokioIo::new(stream), service)
+        .await
+    {
+        tracing::debug!(?error, "mock Runtime API connection closed with an error");
+    }
+}
+
+async fn handle(state: Arc<State>, req: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    let path = req.uri().path().to_owned();
+    let response = match (req.method(), path.as_str()) {
+        (&Method::GET, NEXT_EVENT_PATH) => next_invocation(&state).await,
+        (&Method::GET, RESTORE_NEXT_PATH) => empty(StatusCode::OK),
+        (&Method::POST, INIT_ERROR_PATH) => match collect(req).await {
+            Ok(request) => {
+                let mut outcomes = state.outcomes.lock().expect("mock Runtime API state poisoned");
+                outcomes.init_errors.push(request);
+                empty(StatusCode::ACCEPTED)
+            }
+            Err(_) => empty(StatusCode::BAD_REQUEST),
+        },
+        (&Method::POST, RESTORE_ERROR_PATH) => match collect(req).await {
+            Ok(request) => {
+                let mut outcomes = state.outcomes.lock().expect("mock Runtime API state poisoned");
+                outcomes.restore_errors.push(request);
+                empty(StatusCode::ACCEPTED)
+            }
+            Err(_) => empty(StatusCode::BAD_REQUEST),
+        },
+        (&Method::POST, path) => match path
+            .strip_prefix(INVOCATION_PATH_PREFIX)
+            .and_then(|p| p.split_once('/'))
+        {
+            Some((request_id, "response")) => complete(&state, request_id, req, InvocationOutcome::Response).await,
+            Some((request_id, "error")) => complete(&state, request_id, req, InvocationOutcome::Error).await,
+            _ => empty(StatusCode::NOT_FOUND),
+        },
+        _ => empty(StatusCode::NOT_FOUND),
+    };
+    Ok(response)
+}
+
+async fn next_invocation(state: &State) -> Response<Full<Bytes>> {
+    let invocation = state.receiver.lock().await.recv().await;
+    let Some(invocation) = invocation else {
+        return empty(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+
+    let mut response = Response::new(Full::new(invocation.body));
+    *response.headers_mut() = invocation.headers;
+    let headers = response.headers_mut();
+    headers.insert(
+        "lambda-runtime-aws-request-id",
+        HeaderValue::from_str(&invocation.request_id).expect("invalid request id"),
+    );
+    headers.insert(http::header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+async fn complete(
+    state: &State,
+    request_id: &str,
+    req: Request<Incoming>,
+    outcome: fn(PostedRequest) -> InvocationOutcome,
+) -> Response<Full<Bytes>> {
+    let sender = state
+        .outcomes
+        .lock()
+        .expect("mock Runtime API state poisoned")
+        .pending
+        .remove(request_id);
+    let Some(sender) = sender else {
+        return empty(StatusCode::BAD_REQUEST);
+    };
+
+    match collect(req).await {
+        Ok(request) => {
+            // The test that queued the invocation might have stopped waiting for it.
+            let _ = sender.send(outcome(request));
+            empty(StatusCode::ACCEPTED)
+        }
+        Err(_) => empty(StatusCode::BAD_REQUEST),
+    }
+}
+
+async fn collect(req: Request<Incoming>) -> Result<PostedRequest, hyper::Error> {
+    let (parts, body) = req.into_parts();
+    let collected = body.collect().await?;
+
+    let mut headers = parts.headers;
+    if let Some(trailers) = collected.trailers() {
+        headers.extend(trailers.clone());
+    }
+    Ok(PostedRequest {
+        headers,
+        body: collected.to_bytes(),
+    })
+}
+
+fn test_config() -> Config {
+    Config {
+        function_name: "test_fn".to_string(),
+        memory: 128,
+        version: "$LATEST".to_string(),
+        log_stream: "test_stream".to_string(),
+        log_group: "test_log".to_string(),
+    }
+}
+
+fn empty(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::default());
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{response_size::ResponseSizeGuard, service_fn, Context, Hooks};
+    use serde_json::{json, Value};
+    use tokio::time::timeout;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn test_successful_invocation() -> Result<(), Error> {
+        let api = MockRuntimeApi::start().await?;
+        let runtime = api.runtime(service_fn(|event: LambdaEvent<Value>| async move {
+            let (payload, context) = event.into_parts();
+            Ok::<_, Error>(json!({ "payload": payload, "requestId": context.request_id }))
+        }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        let invocation = MockInvocation::json(&json!({ "command": "hello" }))?.with_request_id("my-id");
+        let outcome = timeout(TEST_TIMEOUT, api.invoke(invocation)).await??;
+        let response: Value = outcome.into_response()?;
+        assert_eq!(
+            response,
+            json!({ "payload": { "command": "hello" }, "requestId": "my-id" })
+        );
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_transport() -> Result<(), Error> {
+        let api = MockRuntimeApi::start_in_memory();
+        let runtime = api.runtime(service_fn(|event: LambdaEvent<Value>| async move {
+            Ok::<_, Error>(event.payload)
+        }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        for i in 0..3 {
+            let outcome = timeout(TEST_TIMEOUT, api.invoke(MockInvocation::json(&json!({ "i": i }))?)).await??;
+            let response: Value = outcome.into_response()?;
+            assert_eq!(response, json!({ "i": i }));
+        }
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_interceptors() -> Result<(), Error> {
+        use crate::interceptor::{Exchange, Interceptor};
+
+        struct RecordPaths(mpsc::UnboundedSender<(String, u64)>);
+
+        impl Interceptor for RecordPaths {
+            fn on_response(&self, exchange: &Exchange, _response: &Response<Incoming>) {
+                let _ = self.0.send((exchange.uri.path().to_owned(), exchange.bytes_sent));
+            }
+        }
+
+        let (sender, mut paths) = mpsc::unbounded_channel();
+        let api = MockRuntimeApi::start().await?;
+        let runtime = RuntimeBuilder::new()
+            .with_endpoint(api.endpoint())
+            .with_config(test_config())
+            .with_concurrency(1)
+            .with_interceptor(RecordPaths(sender))
+            .build(service_fn(|event: LambdaEvent<Value>| async move {
+                Ok::<_, Error>(event.payload)
+            }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        let invocation = MockInvocation::json(&json!("hello"))?.with_request_id("my-id");
+        timeout(TEST_TIMEOUT, api.invoke(invocation)).await??;
+        let next = timeout(TEST_TIMEOUT, paths.recv()).await?;
+        assert_eq!(next, Some((NEXT_EVENT_PATH.to_owned(), 0)));
+        let response = timeout(TEST_TIMEOUT, paths.recv()).await?;
+        assert_eq!(
+            response,
+            Some(("/2018-06-01/runtime/invocation/my-id/response".to_owned(), 7))
+        );
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invocation_context() -> Result<(), Error> {
+        let api = MockRuntimeApi::start().await?;
+        let runtime = api.runtime(service_fn(|event: LambdaEvent<Value>| async move {
+            let context: Context = event.context;
+            Ok::<_, Error>(json!({
+                "arn": context.invoked_function_arn,
+                "traceId": context.xray_trace_id,
+                "functionName": context.env_config.function_name,
+            }))
+        }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        let invocation = MockInvocation::new("{}")
+            .with_invoked_function_arn("arn:aws:lambda:eu-west-1:123456789012:function:other")
+            .with_xray_trace_id("Root=1-5759e988-bd862e3fe1be46a994272793");
+        let response: Value = timeout(TEST_TIMEOUT, api.invoke(invocation)).await??.into_response()?;
+        assert_eq!(
+            response,
+            json!({
+                "arn": "arn:aws:lambda:eu-west-1:123456789012:function:other",
+                "traceId": "Root=1-5759e988-bd862e3fe1be46a994272793",
+                "functionName": "test_fn",
+            })
+        );
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_invocation() -> Result<(), Error> {
+        let api = MockRuntimeApi::start().await?;
+        let runtime = api.runtime(service_fn(|_: LambdaEvent<Value>| async move {
+            Err::<Value, _>(Error::from("something went wrong"))
+        }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        let outcome = timeout(TEST_TIMEOUT, api.invoke(MockInvocation::new("{}"))).await??;
+        let error = match &outcome {
+            InvocationOutcome::Error(request) => request.clone(),
+            InvocationOutcome::Response(_) => panic!("expected an error outcome"),
+        };
+        assert_eq!(error.headers["lambda-runtime-function-error-type"], "unhandled");
+
+        let diagnostic = outcome.into_diagnostic()?;
+        assert_eq!(diagnostic.error_message, "something went wrong");
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_xray_error_cause() -> Result<(), Error> {
+        let api = MockRuntimeApi::start().await?;
+        let runtime = RuntimeBuilder::new()
+            .with_client(api.client()?)
+            .with_config(test_config())
+            .with_concurrency(1)
+            .with_xray_error_cause(true)
+            .build(service_fn(|_: LambdaEvent<Value>| async move {
+                Err::<Value, _>(Error::from("something went wrong"))
+            }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        let invocation = MockInvocation::new("{}").with_xray_trace_id("Root=1-5759e988-bd862e3fe1be46a994272793");
+        let outcome = timeout(TEST_TIMEOUT, api.invoke(invocation)).await??;
+        let error = match outcome {
+            InvocationOutcome::Error(request) => request,
+            InvocationOutcome::Response(_) => panic!("expected an error outcome"),
+        };
+        let cause: Value =
+            serde_json::from_slice(error.headers["lambda-runtime-function-xray-error-cause"].as_bytes())?;
+        assert_eq!(cause["exceptions"][0]["message"], "something went wrong");
+
+        // Invocations that are not traced don't send the error cause.
+        let outcome = timeout(TEST_TIMEOUT, api.invoke(MockInvocation::new("{}"))).await??;
+        let error = match outcome {
+            InvocationOutcome::Error(request) => request,
+            InvocationOutcome::Response(_) => panic!("expected an error outcome"),
+        };
+        assert!(!error.headers.contains_key("lambda-runtime-function-xray-error-cause"));
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_raw_codec() -> Result<(), Error> {
+        let api = MockRuntimeApi::start().await?;
+        let runtime = RuntimeBuilder::new()
+            .with_client(api.client()?)
+            .with_config(test_config())
+            .with_concurrency(1)
+            .with_codec(crate::codec::RawCodec)
+            .build(service_fn(|event: LambdaEvent<Bytes>| async move {
+                Ok::<_, Error>(event.payload.to_ascii_uppercase())
+            }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        let outcome = timeout(TEST_TIMEOUT, api.invoke(MockInvocation::new("not json"))).await??;
+        match outcome {
+            InvocationOutcome::Response(response) => assert_eq!(response.body, Bytes::from("NOT JSON")),
+            InvocationOutcome::Error(_) => panic!("expected a response outcome"),
+        }
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_borrowed_payload() -> Result<(), Error> {
+        #[derive(Deserialize)]
+        struct Order<'a> {
+            id: &'a str,
+        }
+
+        let api = MockRuntimeApi::start().await?;
+        let runtime = api.runtime(service_fn(|event: LambdaEvent<crate::Borrowed>| async move {
+            let order: Order<'_> = event.payload.deserialize()?;
+            Ok::<_, Error>(json!({ "id": order.id }))
+        }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        let outcome = timeout(
+            TEST_TIMEOUT,
+            api.invoke(MockInvocation::json(&json!({ "id": "order-1" }))?),
+        )
+        .await??;
+        let response: Value = outcome.into_response()?;
+        assert_eq!(response, json!({ "id": "order-1" }));
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hooks() -> Result<(), Error> {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let hooks = Hooks::new()
+            .on_init({
+                let calls = calls.clone();
+                move || async move {
+                    calls.lock().unwrap().push("init".to_string());
+                    Ok::<_, Error>(())
+                }
+            })
+            .before_invoke({
+                let calls = calls.clone();
+                move |context| calls.lock().unwrap().push(format!("before {}", context.request_id))
+            })
+            .after_invoke({
+                let calls = calls.clone();
+                move |context, result| {
+                    calls
+                        .lock()
+                        .unwrap()
+                        .push(format!("after {} {}", context.request_id, result.is_ok()))
+                }
+            });
+
+        let api = MockRuntimeApi::start().await?;
+        let runtime = RuntimeBuilder::new()
+            .with_client(api.client()?)
+            .with_config(test_config())
+            .with_concurrency(1)
+            .with_hooks(hooks)
+            .with_response_size_guard(ResponseSizeGuard::new().with_limit(16))
+            .build(service_fn(|event: LambdaEvent<Value>| async move {
+                match event.payload {
+                    Value::Bool(true) | Value::String(_) => Ok(event.payload),
+                    _ => Err(Error::from("unexpected payload")),
+                }
+            }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        let invocation = MockInvocation::new("true").with_request_id("ok");
+        timeout(TEST_TIMEOUT, api.invoke(invocation)).await??;
+        let invocation = MockInvocation::new("false").with_request_id("err");
+        timeout(TEST_TIMEOUT, api.invoke(invocation)).await??;
+        // The handler succeeds, but the response exceeds the size limit.
+        let invocation = MockInvocation::json(&"a response larger than the limit")?.with_request_id("large");
+        let outcome = timeout(TEST_TIMEOUT, api.invoke(invocation)).await??;
+        assert!(outcome.is_error());
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "init",
+                "before ok",
+                "after ok true",
+                "before err",
+                "after err false",
+                "before large",
+                "after large false"
+            ]
+        );
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_init_hook_failure() -> Result<(), Error> {
+        let api = MockRuntimeApi::start().await?;
+        let runtime = RuntimeBuilder::new()
+            .with_client(api.client()?)
+            .with_config(test_config())
+            .with_hooks(Hooks::new().on_init(|| async { Err::<(), _>(Error::from("missing table")) }))
+            .build(service_fn(|event: LambdaEvent<Value>| async move {
+                Ok::<_, Error>(event.payload)
+            }))?;
+
+        let result = timeout(TEST_TIMEOUT, runtime.run()).await?;
+        assert!(result.is_err());
+
+        let init_errors = api.init_errors();
+        assert_eq!(init_errors.len(), 1);
+        let diagnostic: Diagnostic = init_errors[0].json()?;
+        assert_eq!(diagnostic.error_message, "missing table");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snap_start() -> Result<(), Error> {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let hook = |name: &'static str| {
+            let calls = calls.clone();
+            move || async move {
+                calls.lock().unwrap().push(name);
+                Ok::<_, Error>(())
+            }
+        };
+        let hooks = Hooks::new()
+            .after_restore(hook("after restore"))
+            .before_checkpoint(hook("before checkpoint"))
+            .on_init(hook("init"));
+
+        let api = MockRuntimeApi::start().await?;
+        let runtime = RuntimeBuilder::new()
+            .with_client(api.client()?)
+            .with_config(test_config())
+            .with_concurrency(1)
+            .with_snap_start(true)
+            .with_hooks(hooks)
+            .build(service_fn(|event: LambdaEvent<Value>| async move {
+                Ok::<_, Error>(event.context.initialization_type.as_str().to_owned())
+            }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        let outcome = timeout(TEST_TIMEOUT, api.invoke(MockInvocation::new("{}"))).await??;
+        assert_eq!(outcome.into_response::<String>()?, "snap-start");
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["init", "before checkpoint", "after restore"]
+        );
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_hook_failure() -> Result<(), Error> {
+        let api = MockRuntimeApi::start().await?;
+        let runtime = RuntimeBuilder::new()
+            .with_client(api.client()?)
+            .with_config(test_config())
+            .with_snap_start(true)
+            .with_hooks(Hooks::new().after_restore(|| async { Err::<(), _>(Error::from("connection refused")) }))
+            .build(service_fn(|event: LambdaEvent<Value>| async move {
+                Ok::<_, Error>(event.payload)
+            }))?;
+
+        let err = timeout(TEST_TIMEOUT, runtime.run()).await?.unwrap_err();
+        assert!(err.to_string().contains("connection refused"), "{err}");
+        assert!(api.init_errors().is_empty());
+
+        let restore_errors = api.restore_errors();
+        assert_eq!(restore_errors.len(), 1);
+        let diagnostic: Diagnostic = restore_errors[0].json()?;
+        assert_eq!(diagnostic.error_message, "connection refused");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_payload() -> Result<(), Error> {
+        let api = MockRuntimeApi::start().await?;
+        let runtime = api.runtime(service_fn(|event: LambdaEvent<Vec<String>>| async move {
+            Ok::<_, Error>(event.payload)
+        }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        let outcome = timeout(TEST_TIMEOUT, api.invoke(MockInvocation::new("{}"))).await??;
+        let diagnostic = outcome.into_diagnostic()?;
+        assert!(diagnostic
+            .error_message
+            .starts_with("failed to deserialize the incoming data into the function's payload type"));
+        assert_eq!(diagnostic.causes.len(), 1);
+        assert!(diagnostic.causes[0].starts_with("invalid type: map"));
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sequential_invocations() -> Result<(), Error> {
+        let api = MockRuntimeApi::start().await?;
+        let runtime = api.runtime(service_fn(|event: LambdaEvent<u32>| async move {
+            Ok::<_, Error>(event.payload * 2)
+        }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        for n in 0..3 {
+            let outcome = timeout(TEST_TIMEOUT, api.invoke(MockInvocation::json(&n)?)).await??;
+            assert_eq!(outcome.into_response::<u32>()?, n * 2);
+        }
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invocation_sequence() -> Result<(), Error> {
+        let api = MockRuntimeApi::start().await?;
+        let runtime = api.runtime(service_fn(|event: LambdaEvent<Value>| async move {
+            let context = event.context;
+            Ok::<_, Error>((context.invocation_sequence, context.is_cold_start()))
+        }))?;
+        let handle = tokio::spawn(runtime.run());
+
+        for expected in [(1, true), (2, false), (3, false)] {
+            let outcome = timeout(TEST_TIMEOUT, api.invoke(MockInvocation::new("{}"))).await??;
+            assert_eq!(outcome.into_response::<(u64, bool)>()?, expected);
+        }
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_init_error() -> Result<(), Error> {
+        let api = MockRuntimeApi::start().await?;
+        let diagnostic = Diagnostic {
+            error_type: "InitError".into(),
+            error_message: "unable to connect to the database".into(),
+            ..Default::default()
+        };
+        RuntimeBuilder::new()
+            .with_client(api.client()?)
+            .report_init_error(diagnostic.clone())
+            .await?;
+
+        let init_errors = api.init_errors();
+        assert_eq!(init_errors.len(), 1);
+        assert_eq!(
+            init_errors[0].headers["lambda-runtime-function-error-type"],
+            "unhandled"
+        );
+        assert_eq!(init_errors[0].json::<Diagnostic>()?, diagnostic);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_invocations() -> Result<(), Error> {
+        // Both invocations must be in flight at the same time to get past the barrier.
+        let barrier = Arc::new(tokio::sync::Barrier::new(2));
+        let api = MockRuntimeApi::start().await?;
+        let runtime = api
+            .runtime(service_fn(move |event: LambdaEvent<Value>| {
+                let barrier = barrier.clone();
+                async move {
+                    barrier.wait().await;
+                    Ok::<_, Error>(json!({ "requestId": event.context.request_id }))
+                }
+            }))?
+            .with_concurrency(2);
+        let handle = tokio::spawn(runtime.run_concurrent());
+
+        let first = MockInvocation::new("{}").with_request_id("first");
+        let second = MockInvocation::new("{}").with_request_id("second");
+        let (first, second) = timeout(TEST_TIMEOUT, async {
+            tokio::try_join!(api.invoke(first), api.invoke(second))
+        })
+        .await??;
+        assert_eq!(first.into_response::<Value>()?, json!({ "requestId": "first" }));
+        assert_eq!(second.into_response::<Value>()?, json!({ "requestId": "second" }));
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_handler() -> Result<(), Error> {
+        use crate::{streaming, StreamResponse};
+        use std::{cell::RefCell, rc::Rc};
+
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                // The handler future holds `Rc` state across await points, so it's not `Send`.
+                let seen = Rc::new(RefCell::new(Vec::new()));
+                let api = MockRuntimeApi::start().await?;
+                let runtime = api.runtime(service_fn(move |event: LambdaEvent<Value>| {
+                    let seen = seen.clone();
+                    async move {
+                        seen.borrow_mut().push(event.context.request_id);
+                        tokio::task::yield_now().await;
+
+                        let (mut tx, body) = streaming::channel();
+                        let seen = seen.clone();
+                        tokio::task::spawn_local(async move {
+                            let chunk = seen.borrow().join(",");
+                            tx.send_data(chunk.into()).await.unwrap();
+                        });
+                        Ok::<_, Error>(StreamResponse::from(body))
+                    }
+                }))?;
+                let handle = tokio::task::spawn_local(runtime.run());
+
+                timeout(
+                    TEST_TIMEOUT,
+                    api.invoke(MockInvocation::new("{}").with_request_id("first")),
+                )
+                .await??;
+                let outcome = timeout(
+                    TEST_TIMEOUT,
+                    api.invoke(MockInvocation::new("{}").with_request_id("second")),
+                )
+                .await??;
+                let InvocationOutcome::Response(response) = outcome else {
+                    panic!("expected a function response");
+                };
+                assert!(response.body.ends_with(b"first,second"));
+
+                handle.abort();
+                Ok(())
+            })
+            .await
+    }
+}
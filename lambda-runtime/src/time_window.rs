@@ -0,0 +1,361 @@
+//! Aggregate the records of Kinesis and DynamoDB streams over
+//! [tumbling windows](https://docs.aws.amazon.com/lambda/latest/dg/with-kinesis.html#services-kinesis-windows)
+//! with a typed state.
+//!
+//! With tumbling windows, Lambda gives the state returned by an invocation to the next invocation
+//! of the same window, as a map of strings. A [WindowProcessor] keeps a typed [Aggregator::State]
+//! in that map instead: it deserializes the state of the window, [folds](Aggregator::fold) every
+//! record into it, and serializes it into the response. When the window ends, the state is
+//! given to [Aggregator::on_final], and the next window starts with a fresh state.
+//!
+//! When a record can't be folded, the invocation fails, and Lambda retries the batch with the
+//! state of the previous invocation, so records are never counted twice.
+//!
+//! # Example
+//! ```no_run
+//! use aws_lambda_events::{
+//!     kinesis::{KinesisEventRecord, KinesisTimeWindowEvent},
+//!     time_window::TimeWindowProperties,
+//! };
+//! use lambda_runtime::{
+//!     time_window::{Aggregator, WindowProcessor},
+//!     Error,
+//! };
+//! use std::future::Future;
+//!
+//! struct CountRecords;
+//!
+//! impl Aggregator<KinesisEventRecord> for CountRecords {
+//!     type State = u64;
+//!     type Error = Error;
+//!
+//!     fn fold(&self, state: &mut u64, _record: KinesisEventRecord) -> Result<(), Error> {
+//!         *state += 1;
+//!         Ok(())
+//!     }
+//!
+//!     fn on_final(&self, window: &TimeWindowProperties, state: u64) -> impl Future<Output = Result<(), Error>> + Send {
+//!         let start = window.window.start;
+//!         async move {
+//!             println!("{state} records in the window starting at {start}");
+//!             Ok(())
+//!         }
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let processor = WindowProcessor::new(CountRecords);
+//!     lambda_runtime::run(processor.for_event::<KinesisTimeWindowEvent>()).await
+//! }
+//! ```
+use crate::{Diagnostic, LambdaEvent};
+use aws_lambda_events::{
+    dynamodb,
+    kinesis::{KinesisEventRecord, KinesisTimeWindowEvent, KinesisTimeWindowEventResponse},
+    time_window::{TimeWindowEventResponseProperties, TimeWindowProperties},
+};
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashMap, future::Future, marker::PhantomData, sync::Arc, task};
+use tower::Service;
+use tracing::{debug, error};
+
+/// Key of the serialized state in the state map of the window.
+const STATE_KEY: &str = "state";
+
+/// Error type reported to Lambda when the state of the window can't be serialized or deserialized.
+const STATE_ERROR_TYPE: &str = "Runtime.TimeWindowStateError";
+
+/// Aggregates the records of type `R` of a time window into a typed state.
+pub trait Aggregator<R> {
+    /// State of the window. A window starts with the default state.
+    type State: Serialize + DeserializeOwned + Default;
+    /// Error returned when a record can't be aggregated.
+    type Error: Into<Diagnostic>;
+
+    /// Add a record to the state of the window.
+    fn fold(&self, state: &mut Self::State, record: R) -> Result<(), Self::Error>;
+
+    /// Handle the state of a window once it ends, after its last records are folded.
+    ///
+    /// It's called for the final invocation of the window, and when Lambda terminates the
+    /// window early, because its state is too large. In that case, the window continues with
+    /// a fresh state, and `on_final` is called again when it ends.
+    fn on_final(
+        &self,
+        window: &TimeWindowProperties,
+        state: Self::State,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+/// Stream events with time window properties.
+///
+/// It's implemented for the Kinesis and DynamoDB time window events of [aws_lambda_events].
+pub trait WindowEvent {
+    /// Type of the records of the batch.
+    type Record;
+    /// Type of the response with the state of the window.
+    type Response;
+
+    /// Take the properties of the window and the records of the batch.
+    fn into_parts(self) -> (TimeWindowProperties, Vec<Self::Record>);
+
+    /// Build the response with the state of the window.
+    fn response(state: HashMap<String, String>) -> Self::Response;
+}
+
+impl WindowEvent for KinesisTimeWindowEvent {
+    type Record = KinesisEventRecord;
+    type Response = KinesisTimeWindowEventResponse;
+
+    fn into_parts(self) -> (TimeWindowProperties, Vec<Self::Record>) {
+        (self.time_window_properties, self.kinesis_event.records)
+    }
+
+    // The response has an extra field when the `catch-all-fields` feature of aws_lambda_events is enabled.
+    #[allow(clippy::needless_update)]
+    fn response(state: HashMap<String, String>) -> Self::Response {
+        KinesisTimeWindowEventResponse {
+            time_window_event_response_properties: TimeWindowEventResponseProperties { state },
+            ..Default::default()
+        }
+    }
+}
+
+impl WindowEvent for dynamodb::TimeWindowEvent {
+    type Record = dynamodb::EventRecord;
+    type Response = dynamodb::TimeWindowEventResponse;
+
+    fn into_parts(self) -> (TimeWindowProperties, Vec<Self::Record>) {
+        (self.time_window_properties, self.dynamo_db_event.records)
+    }
+
+    #[allow(clippy::needless_update)]
+    fn response(state: HashMap<String, String>) -> Self::Response {
+        dynamodb::TimeWindowEventResponse {
+            time_window_event_response_properties: TimeWindowEventResponseProperties { state },
+            ..Default::default()
+        }
+    }
+}
+
+/// Folds the records of time window events with an [Aggregator], and keeps its state between
+/// the invocations of a window. See the [module documentation](self) for more details.
+pub struct WindowProcessor<A> {
+    aggregator: Arc<A>,
+}
+
+impl<A> Clone for WindowProcessor<A> {
+    fn clone(&self) -> Self {
+        Self {
+            aggregator: self.aggregator.clone(),
+        }
+    }
+}
+
+impl<A> WindowProcessor<A> {
+    /// Create a new processor that aggregates the records with `aggregator`.
+    pub fn new(aggregator: A) -> Self {
+        Self {
+            aggregator: Arc::new(aggregator),
+        }
+    }
+
+    /// Create a service that processes the events of type `E`, for [run](crate::run).
+    pub fn for_event<E>(self) -> WindowService<A, E> {
+        WindowService {
+            processor: self,
+            _event: PhantomData,
+        }
+    }
+
+    /// Fold the records of an event into the state of its window, and build the response
+    /// with the new state.
+    pub async fn process<E>(&self, event: E) -> Result<E::Response, Diagnostic>
+    where
+        E: WindowEvent,
+        A: Aggregator<E::Record>,
+    {
+        let (properties, records) = event.into_parts();
+        let mut state = match properties.state.get(STATE_KEY) {
+            Some(state) => serde_json::from_str(state).map_err(|err| state_error("deserialize", err))?,
+            None => A::State::default(),
+        };
+        debug!(
+            records = records.len(),
+            is_final_invoke_for_window = properties.is_final_invoke_for_window,
+            is_window_terminated_early = properties.is_window_terminated_early,
+            "processing time window event"
+        );
+
+        for record in records {
+            self.aggregator.fold(&mut state, record).map_err(Into::into)?;
+        }
+
+        if properties.is_final_invoke_for_window || properties.is_window_terminated_early {
+            self.aggregator.on_final(&properties, state).await.map_err(Into::into)?;
+            return Ok(E::response(HashMap::new()));
+        }
+
+        let state = serde_json::to_string(&state).map_err(|err| state_error("serialize", err))?;
+        Ok(E::response(HashMap::from([(STATE_KEY.to_owned(), state)])))
+    }
+}
+
+fn state_error(action: &str, err: serde_json::Error) -> Diagnostic {
+    error!(error = ?err, "failed to {action} the state of the time window");
+    Diagnostic {
+        error_type: STATE_ERROR_TYPE.into(),
+        error_message: format!("Failed to {action} the state of the time window: {err}"),
+        ..Default::default()
+    }
+}
+
+/// Tower service returned by [WindowProcessor::for_event].
+pub struct WindowService<A, E> {
+    processor: WindowProcessor<A>,
+    _event: PhantomData<fn(E)>,
+}
+
+impl<A, E> Clone for WindowService<A, E> {
+    fn clone(&self) -> Self {
+        Self {
+            processor: self.processor.clone(),
+            _event: PhantomData,
+        }
+    }
+}
+
+impl<A, E> Service<LambdaEvent<E>> for WindowService<A, E>
+where
+    E: WindowEvent + Send + 'static,
+    E::Record: Send,
+    E::Response: Send,
+    A: Aggregator<E::Record> + Send + Sync + 'static,
+    A::State: Send,
+{
+    type Response = E::Response;
+    type Error = Diagnostic;
+    type Future = BoxFuture<'static, Result<E::Response, Diagnostic>>;
+
+    fn poll_ready(&mut self, _cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: LambdaEvent<E>) -> Self::Future {
+        let processor = self.processor.clone();
+        Box::pin(async move { processor.process(req.payload).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+    struct Totals {
+        records: u64,
+        bytes: usize,
+    }
+
+    #[derive(Default)]
+    struct SumData {
+        windows: Mutex<Vec<Totals>>,
+    }
+
+    impl Aggregator<KinesisEventRecord> for SumData {
+        type State = Totals;
+        type Error = Error;
+
+        fn fold(&self, state: &mut Totals, record: KinesisEventRecord) -> Result<(), Error> {
+            if record.kinesis.data.0.is_empty() {
+                return Err("empty record".into());
+            }
+            state.records += 1;
+            state.bytes += record.kinesis.data.0.len();
+            Ok(())
+        }
+
+        fn on_final(
+            &self,
+            _window: &TimeWindowProperties,
+            state: Totals,
+        ) -> impl Future<Output = Result<(), Error>> + Send {
+            self.windows.lock().unwrap().push(state);
+            async { Ok(()) }
+        }
+    }
+
+    fn event(state: Option<Value>, data: &[&str], is_final: bool) -> KinesisTimeWindowEvent {
+        let records: Vec<_> = data
+            .iter()
+            .enumerate()
+            .map(|(i, data)| {
+                json!({
+                    "kinesis": {
+                        "approximateArrivalTimestamp": 1_700_000_000.0,
+                        "data": data,
+                        "partitionKey": "key",
+                        "sequenceNumber": i.to_string(),
+                    }
+                })
+            })
+            .collect();
+        let state = match state {
+            Some(state) => json!({ STATE_KEY: state.to_string() }),
+            None => json!({}),
+        };
+        serde_json::from_value(json!({
+            "Records": records,
+            "window": { "start": "2020-12-09T07:04:00Z", "end": "2020-12-09T07:06:00Z" },
+            "state": state,
+            "isFinalInvokeForWindow": is_final,
+            "isWindowTerminatedEarly": false,
+        }))
+        .unwrap()
+    }
+
+    fn state(response: &KinesisTimeWindowEventResponse) -> Option<Totals> {
+        let state = response.time_window_event_response_properties.state.get(STATE_KEY)?;
+        Some(serde_json::from_str(state).unwrap())
+    }
+
+    #[tokio::test]
+    async fn fold_records_across_invocations() {
+        let processor = WindowProcessor::new(SumData::default());
+
+        // "aGk=" is "hi", "aGVsbG8=" is "hello"
+        let response = processor
+            .process(event(None, &["aGk=", "aGVsbG8="], false))
+            .await
+            .unwrap();
+        let totals = state(&response).unwrap();
+        assert_eq!(totals, Totals { records: 2, bytes: 7 });
+
+        let previous = serde_json::to_value(&totals).unwrap();
+        let response = processor.process(event(Some(previous), &["aGk="], true)).await.unwrap();
+        assert_eq!(state(&response), None);
+        assert_eq!(
+            *processor.aggregator.windows.lock().unwrap(),
+            [Totals { records: 3, bytes: 9 }]
+        );
+    }
+
+    #[tokio::test]
+    async fn fold_failures() {
+        let processor = WindowProcessor::new(SumData::default());
+        let diagnostic = processor.process(event(None, &["aGk=", ""], false)).await.unwrap_err();
+        assert_eq!(diagnostic.error_message, "empty record");
+
+        let diagnostic = processor
+            .process(event(Some(json!("not totals")), &[], false))
+            .await
+            .unwrap_err();
+        assert_eq!(diagnostic.error_type, STATE_ERROR_TYPE);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Error, RefConfig};
+use crate::{codec::DeserializeError, deserializer, metrics::Metrics, Error, RefConfig};
 use base64::prelude::*;
 use bytes::Bytes;
 use http::{header::ToStrError, HeaderMap, HeaderValue, StatusCode};
@@ -7,7 +7,8 @@ use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     fmt::Debug,
-    time::{Duration, SystemTime},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant, SystemTime},
 };
 use tokio_stream::Stream;
 
@@ -83,6 +84,21 @@ pub struct Context {
     /// Includes information such as the function name, memory allocation,
     /// version, and log streams.
     pub env_config: RefConfig,
+    /// Custom metrics recorded during the invocation. They're written as an EMF log line
+    /// when the [MetricsLayer](crate::layers::MetricsLayer) wraps the handler.
+    #[serde(skip)]
+    pub metrics: Metrics,
+    /// Position of this invocation in the sequence of invocations processed by the
+    /// execution environment, starting at `1`. It's `0` for contexts created outside of the runtime.
+    #[serde(default)]
+    pub invocation_sequence: u64,
+    /// Time elapsed between the initialization of the runtime and the start of this invocation.
+    #[serde(default)]
+    pub time_since_init: Duration,
+    /// How the execution environment was initialized, from the `AWS_LAMBDA_INITIALIZATION_TYPE`
+    /// environment variable.
+    #[serde(default)]
+    pub initialization_type: InitializationType,
 }
 
 impl Default for Context {
@@ -95,6 +111,10 @@ impl Default for Context {
             client_context: None,
             identity: None,
             env_config: std::sync::Arc::new(crate::Config::default()),
+            metrics: Metrics::default(),
+            invocation_sequence: 0,
+            time_since_init: Duration::ZERO,
+            initialization_type: InitializationType::default(),
         }
     }
 }
@@ -135,6 +155,10 @@ impl Context {
             client_context,
             identity,
             env_config,
+            metrics: Metrics::default(),
+            invocation_sequence: 0,
+            time_since_init: Duration::ZERO,
+            initialization_type: InitializationType::default(),
         };
 
         Ok(ctx)
@@ -144,6 +168,101 @@ impl Context {
     pub fn deadline(&self) -> SystemTime {
         SystemTime::UNIX_EPOCH + Duration::from_millis(self.deadline)
     }
+
+    /// Whether this is the first invocation processed by an execution environment initialized on demand.
+    ///
+    /// Execution environments initialized for provisioned concurrency are warmed up before
+    /// receiving any invocation, so their first invocation is not a cold start.
+    pub fn is_cold_start(&self) -> bool {
+        self.invocation_sequence == 1 && self.initialization_type != InitializationType::ProvisionedConcurrency
+    }
+}
+
+/// How an execution environment was initialized.
+/// See the [Lambda environment variables](https://docs.aws.amazon.com/lambda/latest/dg/configuration-envvars.html#configuration-envvars-runtime).
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum InitializationType {
+    /// The execution environment was created to process an invocation.
+    #[default]
+    OnDemand,
+    /// The execution environment was created ahead of time for provisioned concurrency.
+    ProvisionedConcurrency,
+    /// The execution environment was restored from a SnapStart snapshot.
+    SnapStart,
+    /// An initialization type unknown to this version of the runtime.
+    Other(String),
+}
+
+impl InitializationType {
+    /// Read the initialization type from the `AWS_LAMBDA_INITIALIZATION_TYPE` environment variable.
+    /// Defaults to [InitializationType::OnDemand] when the variable is not set.
+    pub fn from_env() -> Self {
+        std::env::var("AWS_LAMBDA_INITIALIZATION_TYPE")
+            .map(Self::from)
+            .unwrap_or_default()
+    }
+
+    /// Value of the initialization type in the `AWS_LAMBDA_INITIALIZATION_TYPE` environment variable.
+    pub fn as_str(&self) -> &str {
+        match self {
+            InitializationType::OnDemand => "on-demand",
+            InitializationType::ProvisionedConcurrency => "provisioned-concurrency",
+            InitializationType::SnapStart => "snap-start",
+            InitializationType::Other(value) => value,
+        }
+    }
+}
+
+impl From<String> for InitializationType {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "on-demand" => InitializationType::OnDemand,
+            "provisioned-concurrency" => InitializationType::ProvisionedConcurrency,
+            "snap-start" => InitializationType::SnapStart,
+            _ => InitializationType::Other(value),
+        }
+    }
+}
+
+impl From<InitializationType> for String {
+    fn from(value: InitializationType) -> Self {
+        value.as_str().to_owned()
+    }
+}
+
+/// Lifecycle of the execution environment, shared by all the invocations that it processes.
+#[derive(Debug)]
+pub(crate) struct Sandbox {
+    init: Instant,
+    initialization_type: InitializationType,
+    invocations: AtomicU64,
+}
+
+impl Sandbox {
+    pub(crate) fn new(initialization_type: InitializationType) -> Self {
+        Sandbox {
+            init: Instant::now(),
+            initialization_type,
+            invocations: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn from_env() -> Self {
+        Self::new(InitializationType::from_env())
+    }
+
+    pub(crate) fn is_snap_start(&self) -> bool {
+        self.initialization_type == InitializationType::SnapStart
+    }
+
+    /// Record the start of a new invocation, and set its lifecycle metadata in the context.
+    pub(crate) fn start_invocation(&self, context: &mut Context) {
+        context.invocation_sequence = self.invocations.fetch_add(1, Ordering::Relaxed) + 1;
+        context.time_since_init = self.init.elapsed();
+        context.initialization_type = self.initialization_type.clone();
+    }
 }
 
 /// Extract the invocation request id from the incoming request.
@@ -175,6 +294,72 @@ impl<T> LambdaEvent<T> {
     }
 }
 
+/// Event payload that is deserialized on demand, borrowing from the buffer of the invocation.
+///
+/// Handlers that receive a `LambdaEvent<Borrowed>` can deserialize the payload into types that
+/// hold references to the original JSON document, like `&str` or `Cow<'_, str>` fields, instead
+/// of allocating every string. The buffer is kept alive for as long as the `Borrowed` value.
+///
+/// Unlike other payload types, the payload is not deserialized before the handler is called.
+/// Deserialization errors are returned by [Borrowed::deserialize] instead.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, Borrowed, Error, LambdaEvent};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Batch<'a> {
+///     #[serde(borrow)]
+///     records: Vec<Record<'a>>,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Record<'a> {
+///     #[serde(rename = "eventID")]
+///     event_id: &'a str,
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     lambda_runtime::run(service_fn(func)).await
+/// }
+///
+/// async fn func(event: LambdaEvent<Borrowed>) -> Result<usize, Error> {
+///     let batch: Batch<'_> = event.payload.deserialize()?;
+///     Ok(batch.records.iter().filter(|record| !record.event_id.is_empty()).count())
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Borrowed {
+    body: Bytes,
+}
+
+impl Borrowed {
+    /// Create a new payload from the raw bytes of an event.
+    pub fn new(body: impl Into<Bytes>) -> Self {
+        Self { body: body.into() }
+    }
+
+    /// Deserialize the JSON payload into a type that can borrow from it.
+    pub fn deserialize<'a, T>(&'a self) -> Result<T, DeserializeError>
+    where
+        T: Deserialize<'a>,
+    {
+        deserializer::deserialize(&self.body)
+    }
+
+    /// Get the raw bytes of the payload.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Get the raw bytes of the payload, without copying them.
+    pub fn into_bytes(self) -> Bytes {
+        self.body
+    }
+}
+
 /// Metadata prelude for a stream response.
 #[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
@@ -279,6 +464,26 @@ mod test {
     use crate::Config;
     use std::sync::Arc;
 
+    #[test]
+    fn borrowed_payload() {
+        #[derive(Deserialize)]
+        struct Record<'a> {
+            id: &'a str,
+        }
+
+        let body = Bytes::from(r#"{"id":"49590338271490256608559692538361571095921575989136588898"}"#);
+        let payload = Borrowed::new(body.clone());
+        let record: Record<'_> = payload.deserialize().unwrap();
+        assert_eq!(record.id, "49590338271490256608559692538361571095921575989136588898");
+
+        // The field points into the original buffer instead of a new allocation.
+        let range = body.as_ptr_range();
+        assert!(range.contains(&record.id.as_ptr()));
+
+        let err = Borrowed::new(r#"{"id":1}"#).deserialize::<Record<'_>>().err().unwrap();
+        assert!(err.to_string().contains("[id]"));
+    }
+
     #[test]
     fn context_with_expected_values_and_types_resolves() {
         let config = Arc::new(Config::default());
@@ -496,4 +701,50 @@ mod test {
 
         assert_eq!(metadata_prelude, deserialized);
     }
+
+    #[test]
+    fn sandbox_lifecycle_metadata() {
+        let sandbox = Sandbox {
+            init: Instant::now(),
+            initialization_type: InitializationType::OnDemand,
+            invocations: AtomicU64::new(0),
+        };
+
+        let mut first = Context::default();
+        sandbox.start_invocation(&mut first);
+        assert_eq!(first.invocation_sequence, 1);
+        assert!(first.is_cold_start());
+
+        let mut second = Context::default();
+        sandbox.start_invocation(&mut second);
+        assert_eq!(second.invocation_sequence, 2);
+        assert!(!second.is_cold_start());
+        assert!(second.time_since_init >= first.time_since_init);
+
+        let provisioned = Context {
+            invocation_sequence: 1,
+            initialization_type: InitializationType::ProvisionedConcurrency,
+            ..Default::default()
+        };
+        assert!(!provisioned.is_cold_start());
+        assert!(!Context::default().is_cold_start());
+    }
+
+    #[test]
+    fn initialization_type_values() {
+        for value in [
+            "on-demand",
+            "provisioned-concurrency",
+            "snap-start",
+            "lambda-managed-instances",
+        ] {
+            let init_type = InitializationType::from(value.to_string());
+            assert_eq!(init_type.as_str(), value);
+            assert_eq!(serde_json::to_value(&init_type).unwrap(), value);
+        }
+        assert_eq!(
+            InitializationType::from("snap-start".to_string()),
+            InitializationType::SnapStart
+        );
+    }
 }
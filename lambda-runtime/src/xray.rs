@@ -0,0 +1,167 @@
+use crate::Diagnostic;
+use http::HeaderValue;
+use serde::Serialize;
+use std::env;
+
+/// Header used to send the X-Ray error cause of a failed invocation to the Lambda Runtime API.
+pub(crate) const XRAY_ERROR_CAUSE_HEADER: &str = "lambda-runtime-function-xray-error-cause";
+
+/// Maximum size of the error cause accepted by the Lambda Runtime API.
+const MAX_ERROR_CAUSE_SIZE: usize = 1024 * 1024;
+
+/// Error cause of a failed invocation, as displayed on the X-Ray segment of the function.
+///
+/// See the [X-Ray segment documents](https://docs.aws.amazon.com/xray/latest/devguide/xray-api-segmentdocuments.html#api-segmentdocuments-errors)
+/// for more details about the format.
+#[derive(Debug, Serialize)]
+pub(crate) struct XRayErrorCause<'a> {
+    working_directory: String,
+    exceptions: Vec<XRayException<'a>>,
+    paths: Vec<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct XRayException<'a> {
+    message: &'a str,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    error_type: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stack: Vec<XRayStackFrame<'a>>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct XRayStackFrame<'a> {
+    label: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<u32>,
+}
+
+impl<'a> XRayErrorCause<'a> {
+    /// Build the error cause from a diagnostic. The error itself is the first exception,
+    /// followed by one exception for each one of its causes.
+    pub(crate) fn new(diagnostic: &'a Diagnostic) -> Self {
+        let stack: Vec<_> = diagnostic.stack_trace.iter().map(|frame| stack_frame(frame)).collect();
+
+        let mut paths = Vec::new();
+        for path in stack.iter().filter_map(|frame| frame.path) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+
+        let mut exceptions = vec![XRayException {
+            message: &diagnostic.error_message,
+            error_type: Some(&diagnostic.error_type),
+            stack,
+        }];
+        exceptions.extend(diagnostic.causes.iter().map(|cause| XRayException {
+            message: cause,
+            error_type: None,
+            stack: Vec::new(),
+        }));
+
+        XRayErrorCause {
+            working_directory: env::current_dir()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            exceptions,
+            paths,
+        }
+    }
+
+    /// Serialize the error cause into a header value. Returns `None` when the error cause
+    /// cannot be serialized, or when it's too large to be accepted by the Lambda Runtime API.
+    pub(crate) fn header_value(&self) -> Option<HeaderValue> {
+        let value = serde_json::to_vec(self).ok()?;
+        if value.len() > MAX_ERROR_CAUSE_SIZE {
+            return None;
+        }
+        HeaderValue::from_bytes(&value).ok()
+    }
+}
+
+/// Split a frame formatted as `label at path:line:column` into its parts.
+fn stack_frame(frame: &str) -> XRayStackFrame<'_> {
+    let Some((label, location)) = frame.rsplit_once(" at ") else {
+        return XRayStackFrame {
+            label: frame,
+            path: None,
+            line: None,
+        };
+    };
+
+    let mut parts = location.rsplitn(3, ':');
+    let (path, line) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(_column), Some(line), Some(path)) => (path, line.parse().ok()),
+        _ => (location, None),
+    };
+    XRayStackFrame {
+        label,
+        path: Some(path),
+        line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn error_cause_from_diagnostic() {
+        let diagnostic = Diagnostic {
+            error_type: "OrderError".into(),
+            error_message: "failed to load order".into(),
+            stack_trace: vec![
+                "my_function::handler at ./src/main.rs:10:5".into(),
+                "my_function::main at ./src/main.rs:20:9".into(),
+                "std::rt::lang_start".into(),
+            ],
+            causes: vec!["connection refused".into()],
+        };
+
+        let cause = XRayErrorCause::new(&diagnostic);
+        let value: Value = serde_json::from_slice(cause.header_value().unwrap().as_bytes()).unwrap();
+        let expected = json!({
+            "working_directory": env::current_dir().unwrap().display().to_string(),
+            "exceptions": [
+                {
+                    "message": "failed to load order",
+                    "type": "OrderError",
+                    "stack": [
+                        {"label": "my_function::handler", "path": "./src/main.rs", "line": 10},
+                        {"label": "my_function::main", "path": "./src/main.rs", "line": 20},
+                        {"label": "std::rt::lang_start"},
+                    ],
+                },
+                {"message": "connection refused"},
+            ],
+            "paths": ["./src/main.rs"],
+        });
+        assert_eq!(expected, value);
+    }
+
+    #[test]
+    fn error_cause_too_large() {
+        let diagnostic = Diagnostic {
+            error_type: "LargeError".into(),
+            error_message: "x".repeat(MAX_ERROR_CAUSE_SIZE),
+            ..Default::default()
+        };
+        assert!(XRayErrorCause::new(&diagnostic).header_value().is_none());
+    }
+
+    #[test]
+    fn frame_without_line() {
+        assert_eq!(
+            stack_frame("handler at <unknown>"),
+            XRayStackFrame {
+                label: "handler",
+                path: Some("<unknown>"),
+                line: None,
+            }
+        );
+    }
+}